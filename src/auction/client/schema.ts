@@ -0,0 +1,130 @@
+/**
+ * Decoder for the Borsh encoded instructions and accounts of the auction
+ * program, driven by schema.json which the program tests keep current
+ */
+
+import BN from 'bn.js';
+import {PublicKey} from '@solana/web3.js';
+import fs from 'fs';
+import path from 'path';
+
+type Definition =
+  | {kind: 'array'; length: number; elements: string}
+  | {kind: 'sequence'; elements: string}
+  | {kind: 'tuple'; elements: string[]}
+  | {kind: 'enum'; variants: [string, string][]}
+  | {kind: 'struct'; fields: [string, string][]};
+
+interface Schema {
+  roots: {name: string; tag: number}[];
+  definitions: Record<string, Definition>;
+}
+
+export const schema: Schema = JSON.parse(
+  fs.readFileSync(path.join(__dirname, 'schema.json'), 'utf8'),
+);
+
+const INTEGERS: Record<string, [number, boolean]> = {
+  u8: [1, false],
+  u16: [2, false],
+  u32: [4, false],
+  u64: [8, false],
+  u128: [16, false],
+  i8: [1, true],
+  i16: [2, true],
+  i32: [4, true],
+  i64: [8, true],
+  i128: [16, true],
+};
+
+class Reader {
+  offset = 0;
+
+  constructor(readonly data: Buffer) {}
+
+  take(length: number): Buffer {
+    if (this.offset + length > this.data.length) {
+      throw new Error('unexpected end of data');
+    }
+    const bytes = this.data.slice(this.offset, this.offset + length);
+    this.offset += length;
+    return bytes;
+  }
+
+  read(declaration: string): any {
+    if (declaration === 'nil') {
+      return null;
+    }
+    if (declaration === 'bool') {
+      return this.take(1)[0] !== 0;
+    }
+    if (declaration === 'string') {
+      return this.take(this.take(4).readUInt32LE(0)).toString('utf8');
+    }
+    if (declaration === 'Pubkey') {
+      return new PublicKey(this.take(32));
+    }
+    const integer = INTEGERS[declaration];
+    if (integer) {
+      const [length, signed] = integer;
+      const value = new BN(this.take(length), 'le');
+      if (length <= 4) {
+        return (signed ? value.fromTwos(length * 8) : value).toNumber();
+      }
+      return signed ? value.fromTwos(length * 8) : value;
+    }
+    const definition = schema.definitions[declaration];
+    if (!definition) {
+      throw new Error(`no schema for ${declaration}`);
+    }
+    switch (definition.kind) {
+      case 'array':
+        if (definition.elements === 'u8') {
+          return this.take(definition.length);
+        }
+        return Array.from({length: definition.length}, () =>
+          this.read(definition.elements),
+        );
+      case 'sequence': {
+        const length = this.take(4).readUInt32LE(0);
+        return Array.from({length}, () => this.read(definition.elements));
+      }
+      case 'tuple':
+        return definition.elements.map(element => this.read(element));
+      case 'enum': {
+        const index = this.take(1)[0];
+        if (index >= definition.variants.length) {
+          throw new Error(`invalid ${declaration} variant ${index}`);
+        }
+        const [variant, payload] = definition.variants[index];
+        if (declaration.startsWith('Option<')) {
+          return variant === 'None' ? null : this.read(payload);
+        }
+        return {variant, value: this.read(payload)};
+      }
+      case 'struct': {
+        const value: Record<string, any> = {};
+        for (const [field, fieldDeclaration] of definition.fields) {
+          value[field] = this.read(fieldDeclaration);
+        }
+        return value;
+      }
+    }
+  }
+}
+
+/**
+ * Decode `name`, one of the schema roots, from the instruction data or
+ * account data that starts with its tag. Accounts written before the Borsh
+ * layouts carry another tag and are rejected.
+ */
+export function decode(name: string, data: Buffer): any {
+  const root = schema.roots.find(root => root.name === name);
+  if (!root) {
+    throw new Error(`${name} is not a schema root`);
+  }
+  if (data[0] !== root.tag) {
+    throw new Error(`${name} is not Borsh encoded, tag ${data[0]}`);
+  }
+  return new Reader(data.slice(1)).read(name);
+}
@@ -0,0 +1,348 @@
+//! In-memory accounts to run the processor in tests. Invocations of the
+//! system and token programs are carried out in place of the runtime, and a
+//! failed instruction leaves the accounts as they were.
+
+use crate::processor::Processor;
+use arrayref::array_ref;
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+    sysvar::{self, Sysvar},
+};
+use spl_token::state::{Account, AccountState, Mint};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Once};
+
+/// `SystemError::AccountAlreadyInUse`, what the system program returns when
+/// creating an account that holds lamports
+pub const ACCOUNT_ALREADY_IN_USE: ProgramError = ProgramError::Custom(0);
+
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let mut infos = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?
+                .clone();
+            let signed = info.is_signer
+                || signers_seeds.iter().any(|seeds| {
+                    Pubkey::create_program_address(seeds, &crate::id()) == Ok(meta.pubkey)
+                });
+            if meta.is_signer && !signed {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            info.is_signer = meta.is_signer;
+            info.is_writable = meta.is_writable;
+            infos.push(info);
+        }
+        if instruction.program_id == system_program::id() {
+            process_system_instruction(&instruction.data, &infos)
+        } else if instruction.program_id == spl_token::id() {
+            spl_token::processor::Processor::process(&spl_token::id(), &infos, &instruction.data)
+        } else {
+            Err(ProgramError::IncorrectProgramId)
+        }
+    }
+}
+
+thread_local! {
+    /// Where the owner of each account is stored. The runtime writes owners
+    /// in place, so every clone of an account info sees the new owner.
+    static OWNERS: RefCell<HashMap<Pubkey, *mut Pubkey>> = RefCell::new(HashMap::new());
+}
+
+fn set_owner(info: &AccountInfo, owner: &Pubkey) {
+    OWNERS.with(|owners| unsafe { *owners.borrow()[info.key] = *owner });
+}
+
+fn set_data(info: &AccountInfo, data: Vec<u8>) {
+    *info.data.borrow_mut() = Box::leak(data.into_boxed_slice());
+}
+
+fn debit(info: &AccountInfo, lamports: u64) -> ProgramResult {
+    let balance = info.lamports();
+    **info.lamports.borrow_mut() = balance
+        .checked_sub(lamports)
+        .ok_or(ProgramError::Custom(1))?;
+    Ok(())
+}
+
+fn credit(info: &AccountInfo, lamports: u64) {
+    **info.lamports.borrow_mut() += lamports;
+}
+
+fn check_system_account(info: &AccountInfo) -> ProgramResult {
+    if *info.owner != system_program::id() || !info.data_is_empty() {
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// The system instructions the program invokes: CreateAccount, Assign,
+/// Transfer and Allocate
+fn process_system_instruction(data: &[u8], infos: &[AccountInfo]) -> ProgramResult {
+    let u64_at = |offset: usize| u64::from_le_bytes(*array_ref![data, offset, 8]);
+    let pubkey_at = |offset: usize| Pubkey::new_from_array(*array_ref![data, offset, 32]);
+    match u32::from_le_bytes(*array_ref![data, 0, 4]) {
+        0 => {
+            let (from, to) = (&infos[0], &infos[1]);
+            if to.lamports() > 0 {
+                return Err(ACCOUNT_ALREADY_IN_USE);
+            }
+            check_system_account(from)?;
+            check_system_account(to)?;
+            debit(from, u64_at(4))?;
+            credit(to, u64_at(4));
+            set_data(to, vec![0; u64_at(12) as usize]);
+            set_owner(to, &pubkey_at(20));
+        }
+        1 => {
+            // an allocated account can still be assigned
+            if *infos[0].owner != system_program::id() {
+                return Err(ProgramError::InvalidArgument);
+            }
+            set_owner(&infos[0], &pubkey_at(4));
+        }
+        2 => {
+            check_system_account(&infos[0])?;
+            debit(&infos[0], u64_at(4))?;
+            credit(&infos[1], u64_at(4));
+        }
+        8 => {
+            check_system_account(&infos[0])?;
+            set_data(&infos[0], vec![0; u64_at(4) as usize]);
+        }
+        _ => return Err(ProgramError::InvalidInstructionData),
+    }
+    Ok(())
+}
+
+/// Accounts of a test, keyed by address. Missing accounts read as empty
+/// system accounts, as they do on chain.
+pub struct Bank {
+    accounts: HashMap<Pubkey, AccountInfo<'static>>,
+    pub clock: Clock,
+    pub rent: Rent,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            set_syscall_stubs(Box::new(Stubs));
+        });
+        let mut bank = Self {
+            accounts: HashMap::new(),
+            clock: Clock::default(),
+            rent: Rent::default(),
+        };
+        bank.set_account(
+            sysvar::clock::id(),
+            1,
+            vec![0; Clock::size_of()],
+            sysvar::id(),
+        );
+        bank.set_account(
+            sysvar::rent::id(),
+            1,
+            vec![0; Rent::size_of()],
+            sysvar::id(),
+        );
+        let mut native_mint = vec![0; Mint::LEN];
+        Mint::pack(
+            Mint {
+                decimals: 9,
+                is_initialized: true,
+                ..Mint::default()
+            },
+            &mut native_mint,
+        )
+        .unwrap();
+        bank.set_account(
+            spl_token::native_mint::id(),
+            1,
+            native_mint,
+            spl_token::id(),
+        );
+        bank
+    }
+}
+
+impl Bank {
+    /// Write an account, replacing any account at `key`
+    pub fn set_account(&mut self, key: Pubkey, lamports: u64, data: Vec<u8>, owner: Pubkey) {
+        let owner = Box::into_raw(Box::new(owner));
+        OWNERS.with(|owners| owners.borrow_mut().insert(key, owner));
+        let info = AccountInfo {
+            key: Box::leak(Box::new(key)),
+            is_signer: false,
+            is_writable: false,
+            lamports: Rc::new(RefCell::new(Box::leak(Box::new(lamports)))),
+            data: Rc::new(RefCell::new(Box::leak(data.into_boxed_slice()))),
+            owner: unsafe { &*owner },
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.accounts.insert(key, info);
+    }
+
+    pub fn account(&mut self, key: &Pubkey) -> &AccountInfo<'static> {
+        if !self.accounts.contains_key(key) {
+            self.set_account(*key, 0, vec![], system_program::id());
+        }
+        &self.accounts[key]
+    }
+
+    pub fn lamports(&mut self, key: &Pubkey) -> u64 {
+        self.account(key).lamports()
+    }
+
+    pub fn owner(&mut self, key: &Pubkey) -> Pubkey {
+        *self.account(key).owner
+    }
+
+    pub fn data(&mut self, key: &Pubkey) -> Vec<u8> {
+        self.account(key).data.borrow().to_vec()
+    }
+
+    /// Add lamports to a wallet, creating it if needed
+    pub fn airdrop(&mut self, key: &Pubkey, lamports: u64) {
+        credit(self.account(key), lamports);
+    }
+
+    pub fn create_mint(&mut self) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(
+            Mint {
+                is_initialized: true,
+                ..Mint::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        let lamports = self.rent.minimum_balance(Mint::LEN);
+        self.set_account(key, lamports, data, spl_token::id());
+        key
+    }
+
+    /// Create a token account of `mint` holding `amount`. Accounts of the
+    /// native mint hold it in lamports above their rent.
+    pub fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let rent = self.rent.minimum_balance(Account::LEN);
+        let native = *mint == spl_token::native_mint::id();
+        let mut data = vec![0; Account::LEN];
+        Account::pack(
+            Account {
+                mint: *mint,
+                owner: *owner,
+                amount,
+                state: AccountState::Initialized,
+                is_native: if native {
+                    COption::Some(rent)
+                } else {
+                    COption::None
+                },
+                ..Account::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        let lamports = if native { rent + amount } else { rent };
+        self.set_account(key, lamports, data, spl_token::id());
+        key
+    }
+
+    pub fn token_balance(&mut self, key: &Pubkey) -> u64 {
+        Account::unpack(&self.account(key).data.borrow())
+            .unwrap()
+            .amount
+    }
+
+    /// Process an instruction of the auction program as a transaction of its
+    /// own. Signers are taken from the account metas.
+    pub fn process(&mut self, instruction: &Instruction) -> ProgramResult {
+        let mut clock_info = self.account(&sysvar::clock::id()).clone();
+        self.clock.to_account_info(&mut clock_info).unwrap();
+        let mut rent_info = self.account(&sysvar::rent::id()).clone();
+        self.rent.to_account_info(&mut rent_info).unwrap();
+
+        // an account listed twice is a signer, or writable, if any of its
+        // metas is
+        let infos: Vec<_> = instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let metas = instruction
+                    .accounts
+                    .iter()
+                    .filter(|m| m.pubkey == meta.pubkey);
+                let mut info = self.account(&meta.pubkey).clone();
+                info.is_signer = metas.clone().any(|m| m.is_signer);
+                info.is_writable = metas.clone().any(|m| m.is_writable);
+                info
+            })
+            .collect();
+        let snapshot: Vec<_> = self
+            .accounts
+            .values()
+            .map(|info| {
+                (
+                    info.clone(),
+                    info.lamports(),
+                    info.data.borrow().to_vec(),
+                    *info.owner,
+                )
+            })
+            .collect();
+        let total_lamports = |bank: &Self| {
+            bank.accounts
+                .values()
+                .map(|info| info.lamports())
+                .sum::<u64>()
+        };
+        let lamports_before = total_lamports(self);
+
+        let result = Processor::process(&instruction.program_id, &infos, &instruction.data);
+        if result.is_err() {
+            for (info, lamports, data, owner) in snapshot {
+                **info.lamports.borrow_mut() = lamports;
+                set_data(&info, data);
+                set_owner(&info, &owner);
+            }
+            return result;
+        }
+        assert_eq!(
+            total_lamports(self),
+            lamports_before,
+            "lamports were created or destroyed"
+        );
+        // accounts left without lamports are deleted at the end of the
+        // transaction
+        for info in self.accounts.values() {
+            if info.lamports() == 0 {
+                set_data(info, vec![]);
+                set_owner(info, &system_program::id());
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,196 @@
+//! Dutch auction price, combining a price range with a curve
+
+use crate::curve::{
+    calculator::{DutchCurve, PriceCurve, CURVE_PARAMS_LEN},
+    exponential::ExponentialCurve,
+    linear::LinearCurve,
+    stepped::SteppedCurve,
+};
+use crate::error::AuctionError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::{
+    clock::UnixTimestamp,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+};
+use std::convert::TryFrom;
+
+/// Curve type enum for an instruction or state
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PriceCurveType {
+    /// Price falls at a constant rate
+    Linear,
+    /// Price falls by a fixed fraction every period
+    Exponential,
+    /// Price falls linearly in discrete steps
+    Stepped,
+}
+
+impl TryFrom<u8> for PriceCurveType {
+    type Error = ProgramError;
+
+    fn try_from(curve_type: u8) -> Result<Self, Self::Error> {
+        match curve_type {
+            0 => Ok(PriceCurveType::Linear),
+            1 => Ok(PriceCurveType::Exponential),
+            2 => Ok(PriceCurveType::Stepped),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Price of a Dutch auction, falling from `start_price` at the start
+/// timestamp to `floor_price` at the end timestamp
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct DutchPrice {
+    /// Price when the auction starts
+    pub start_price: u64,
+    /// Price when the auction ends
+    pub floor_price: u64,
+    /// Shape of the descent
+    pub curve: DutchCurve,
+}
+
+impl DutchPrice {
+    /// Type of the price curve
+    pub fn curve_type(&self) -> PriceCurveType {
+        match self.curve {
+            DutchCurve::LinearCurve(_) => PriceCurveType::Linear,
+            DutchCurve::ExponentialCurve(_) => PriceCurveType::Exponential,
+            DutchCurve::SteppedCurve(_) => PriceCurveType::Stepped,
+        }
+    }
+
+    /// Price at `now` of an auction running from `start_timestamp` to
+    /// `end_timestamp`
+    pub fn price_at(
+        &self,
+        start_timestamp: UnixTimestamp,
+        end_timestamp: UnixTimestamp,
+        now: UnixTimestamp,
+    ) -> Option<u64> {
+        if now <= start_timestamp {
+            Some(self.start_price)
+        } else if now >= end_timestamp {
+            Some(self.floor_price)
+        } else {
+            self.curve.price(
+                self.start_price,
+                self.floor_price,
+                now.checked_sub(start_timestamp)?,
+                end_timestamp.checked_sub(start_timestamp)?,
+            )
+        }
+    }
+
+    /// Validate that the price falls and the curve parameters are sane
+    pub fn validate(&self) -> Result<(), AuctionError> {
+        if self.start_price < self.floor_price {
+            return Err(AuctionError::InvalidPriceCurve);
+        }
+        self.curve.validate()
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for DutchPrice {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Sealed for DutchPrice {}
+impl Pack for DutchPrice {
+    const LEN: usize = 33;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 33];
+        let (start_price, floor_price, curve_type, params) =
+            mut_array_refs![output, 8, 8, 1, CURVE_PARAMS_LEN];
+        *start_price = self.start_price.to_le_bytes();
+        *floor_price = self.floor_price.to_le_bytes();
+        curve_type[0] = self.curve_type() as u8;
+        self.curve.pack_params(params);
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<DutchPrice, ProgramError> {
+        let input = array_ref![input, 0, 33];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (start_price, floor_price, curve_type, params) =
+            array_refs![input, 8, 8, 1, CURVE_PARAMS_LEN];
+        let curve = match PriceCurveType::try_from(curve_type[0])? {
+            PriceCurveType::Linear => LinearCurve.into(),
+            PriceCurveType::Exponential => ExponentialCurve::unpack_params(params).into(),
+            PriceCurveType::Stepped => SteppedCurve::unpack_params(params).into(),
+        };
+        Ok(Self {
+            start_price: u64::from_le_bytes(*start_price),
+            floor_price: u64::from_le_bytes(*floor_price),
+            curve,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn curves() -> impl Strategy<Value = DutchCurve> {
+        prop_oneof![
+            Just(DutchCurve::from(LinearCurve)),
+            (1i64..10_000, 0u16..=10_000).prop_map(|(period, decay_bps)| {
+                ExponentialCurve { period, decay_bps }.into()
+            }),
+            (1i64..10_000).prop_map(|step_duration| SteppedCurve { step_duration }.into()),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn price_falls_from_start_to_floor(
+            curve in curves(),
+            floor_price in 0u64..u64::MAX / 2,
+            range in 0u64..u64::MAX / 2,
+            duration in 1i64..1_000_000,
+            first in 0i64..1_000_000,
+            second in 0i64..1_000_000,
+        ) {
+            let price = DutchPrice {
+                start_price: floor_price + range,
+                floor_price,
+                curve,
+            };
+            let (earlier, later) = (first.min(second), first.max(second));
+            let earlier_price = price.price_at(0, duration, earlier).unwrap();
+            let later_price = price.price_at(0, duration, later).unwrap();
+            prop_assert!(earlier_price >= later_price);
+            prop_assert!(earlier_price <= price.start_price);
+            prop_assert!(later_price >= price.floor_price);
+            prop_assert_eq!(price.price_at(0, duration, 0), Some(price.start_price));
+            prop_assert_eq!(price.price_at(0, duration, duration), Some(price.floor_price));
+        }
+
+        #[test]
+        fn exponential_tracks_continuous_decay(
+            periods in 0u64..200,
+            decay_bps in 0u16..=5_000,
+        ) {
+            let curve = ExponentialCurve { period: 10, decay_bps };
+            let range = 1_000_000_000_000u64;
+            let elapsed = periods as i64 * 10;
+            let price = curve.price(range, 0, elapsed, i64::MAX).unwrap() as f64;
+            let expected = range as f64 * (1.0 - f64::from(decay_bps) / 10_000.0).powi(periods as i32);
+            prop_assert!((price - expected).abs() <= 1e-6 * range as f64);
+        }
+
+        #[test]
+        fn pack_round_trip(curve in curves(), start_price: u64, floor_price: u64) {
+            let price = DutchPrice { start_price, floor_price, curve };
+            let mut packed = [0u8; DutchPrice::LEN];
+            Pack::pack_into_slice(&price, &mut packed);
+            prop_assert_eq!(DutchPrice::unpack_from_slice(&packed).unwrap(), price);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Price curve trait shared by all Dutch auction curves
+
+use crate::error::AuctionError;
+use enum_dispatch::enum_dispatch;
+use borsh::{
+    schema::{Declaration, Definition},
+    BorshDeserialize, BorshSchema, BorshSerialize,
+};
+use solana_program::clock::UnixTimestamp;
+use std::{collections::HashMap, convert::TryFrom};
+
+use crate::curve::{exponential::ExponentialCurve, linear::LinearCurve, stepped::SteppedCurve};
+
+/// Size of the curve specific parameters when packed
+pub const CURVE_PARAMS_LEN: usize = 16;
+
+/// Trait for the price of a Dutch auction falling from a start price to a
+/// floor price over its duration
+#[enum_dispatch]
+pub trait PriceCurve {
+    /// Price after `elapsed` seconds of an auction lasting `duration` seconds.
+    /// Callers guarantee `0 <= elapsed < duration` and `floor_price <= start_price`.
+    fn price(
+        &self,
+        start_price: u64,
+        floor_price: u64,
+        elapsed: UnixTimestamp,
+        duration: UnixTimestamp,
+    ) -> Option<u64>;
+
+    /// Validate the curve parameters
+    fn validate(&self) -> Result<(), AuctionError>;
+
+    /// Pack the curve parameters into a slice of `CURVE_PARAMS_LEN` bytes
+    fn pack_params(&self, output: &mut [u8; CURVE_PARAMS_LEN]);
+}
+
+/// All supported price curves
+#[enum_dispatch(PriceCurve)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub enum DutchCurve {
+    /// Price falls at a constant rate
+    LinearCurve,
+    /// Price falls by a fixed fraction every period
+    ExponentialCurve,
+    /// Price falls linearly in discrete steps
+    SteppedCurve,
+}
+
+/// Written out rather than derived so that each variant is described by the
+/// curve type itself instead of a wrapper struct
+impl BorshSchema for DutchCurve {
+    fn add_definitions_recursively(definitions: &mut HashMap<Declaration, Definition>) {
+        LinearCurve::add_definitions_recursively(definitions);
+        ExponentialCurve::add_definitions_recursively(definitions);
+        SteppedCurve::add_definitions_recursively(definitions);
+        let variants = vec![
+            ("LinearCurve".to_string(), LinearCurve::declaration()),
+            ("ExponentialCurve".to_string(), ExponentialCurve::declaration()),
+            ("SteppedCurve".to_string(), SteppedCurve::declaration()),
+        ];
+        Self::add_definition(Self::declaration(), Definition::Enum { variants }, definitions);
+    }
+
+    fn declaration() -> Declaration {
+        "DutchCurve".to_string()
+    }
+}
+
+impl Default for DutchCurve {
+    fn default() -> Self {
+        DutchCurve::LinearCurve(LinearCurve)
+    }
+}
+
+/// Linear interpolation from `start_price` to `floor_price` after `elapsed`
+/// out of `duration` seconds
+pub fn interpolate(
+    start_price: u64,
+    floor_price: u64,
+    elapsed: UnixTimestamp,
+    duration: UnixTimestamp,
+) -> Option<u64> {
+    let range = u128::from(start_price.checked_sub(floor_price)?);
+    let drop = range
+        .checked_mul(u128::try_from(elapsed).ok()?)?
+        .checked_div(u128::try_from(duration).ok()?)?;
+    u64::try_from(u128::from(start_price).checked_sub(drop)?).ok()
+}
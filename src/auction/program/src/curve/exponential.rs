@@ -0,0 +1,84 @@
+//! Price falling by a fixed fraction every period
+
+use crate::{
+    curve::calculator::{PriceCurve, CURVE_PARAMS_LEN},
+    error::AuctionError,
+    rules::BASIS_POINTS,
+};
+use arrayref::{array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::clock::UnixTimestamp;
+use std::convert::TryFrom;
+
+/// Fixed point scale of the decay factor
+const PRECISION: u128 = 1_000_000_000_000;
+
+/// Distance between the price and the floor price shrinks by `decay_bps`
+/// every `period` seconds. The floor is only reached at the end.
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct ExponentialCurve {
+    /// Seconds between two price drops
+    pub period: UnixTimestamp,
+    /// Fraction of the distance to the floor price lost every period, in basis points
+    pub decay_bps: u16,
+}
+
+impl ExponentialCurve {
+    /// Unpack the curve parameters
+    pub fn unpack_params(input: &[u8; CURVE_PARAMS_LEN]) -> Self {
+        let (period, decay_bps, _) = array_refs![input, 8, 2, 6];
+        Self {
+            period: i64::from_le_bytes(*period),
+            decay_bps: u16::from_le_bytes(*decay_bps),
+        }
+    }
+
+    /// Remaining fraction of the distance to the floor after `periods`
+    /// periods, scaled by `PRECISION`
+    fn remaining_fraction(&self, mut periods: u64) -> Option<u128> {
+        let mut base = (BASIS_POINTS - u128::from(self.decay_bps))
+            .checked_mul(PRECISION)?
+            / BASIS_POINTS;
+        let mut result = PRECISION;
+        while periods > 0 {
+            if periods & 1 == 1 {
+                result = result.checked_mul(base)? / PRECISION;
+            }
+            base = base.checked_mul(base)? / PRECISION;
+            periods >>= 1;
+        }
+        Some(result)
+    }
+}
+
+impl PriceCurve for ExponentialCurve {
+    fn price(
+        &self,
+        start_price: u64,
+        floor_price: u64,
+        elapsed: UnixTimestamp,
+        _duration: UnixTimestamp,
+    ) -> Option<u64> {
+        let periods = u64::try_from(elapsed.checked_div(self.period)?).ok()?;
+        let range = u128::from(start_price.checked_sub(floor_price)?);
+        let remaining = range
+            .checked_mul(self.remaining_fraction(periods)?)?
+            / PRECISION;
+        u64::try_from(u128::from(floor_price).checked_add(remaining)?).ok()
+    }
+
+    fn validate(&self) -> Result<(), AuctionError> {
+        if self.period <= 0 || u128::from(self.decay_bps) > BASIS_POINTS {
+            Err(AuctionError::InvalidPriceCurve)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn pack_params(&self, output: &mut [u8; CURVE_PARAMS_LEN]) {
+        let (period, decay_bps, padding) = mut_array_refs![output, 8, 2, 6];
+        *period = self.period.to_le_bytes();
+        *decay_bps = self.decay_bps.to_le_bytes();
+        *padding = [0; 6];
+    }
+}
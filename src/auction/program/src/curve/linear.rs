@@ -0,0 +1,32 @@
+//! Price falling at a constant rate
+
+use crate::{
+    curve::calculator::{interpolate, PriceCurve, CURVE_PARAMS_LEN},
+    error::AuctionError,
+};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::clock::UnixTimestamp;
+
+/// Price falls at a constant rate from the start price to the floor price
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct LinearCurve;
+
+impl PriceCurve for LinearCurve {
+    fn price(
+        &self,
+        start_price: u64,
+        floor_price: u64,
+        elapsed: UnixTimestamp,
+        duration: UnixTimestamp,
+    ) -> Option<u64> {
+        interpolate(start_price, floor_price, elapsed, duration)
+    }
+
+    fn validate(&self) -> Result<(), AuctionError> {
+        Ok(())
+    }
+
+    fn pack_params(&self, output: &mut [u8; CURVE_PARAMS_LEN]) {
+        *output = [0; CURVE_PARAMS_LEN];
+    }
+}
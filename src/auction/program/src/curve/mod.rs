@@ -0,0 +1,7 @@
+//! Price curves of descending (Dutch) auctions
+
+pub mod calculator;
+pub mod base;
+pub mod exponential;
+pub mod linear;
+pub mod stepped;
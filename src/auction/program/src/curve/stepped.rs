@@ -0,0 +1,57 @@
+//! Price falling linearly in discrete steps
+
+use crate::{
+    curve::calculator::{interpolate, PriceCurve, CURVE_PARAMS_LEN},
+    error::AuctionError,
+};
+use arrayref::{array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::clock::UnixTimestamp;
+
+/// Price holds for `step_duration` seconds, then drops to where the linear
+/// curve is at that time
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct SteppedCurve {
+    /// Seconds between two price drops
+    pub step_duration: UnixTimestamp,
+}
+
+impl SteppedCurve {
+    /// Unpack the curve parameters
+    pub fn unpack_params(input: &[u8; CURVE_PARAMS_LEN]) -> Self {
+        let (step_duration, _) = array_refs![input, 8, 8];
+        Self {
+            step_duration: i64::from_le_bytes(*step_duration),
+        }
+    }
+}
+
+impl PriceCurve for SteppedCurve {
+    fn price(
+        &self,
+        start_price: u64,
+        floor_price: u64,
+        elapsed: UnixTimestamp,
+        duration: UnixTimestamp,
+    ) -> Option<u64> {
+        let stepped_elapsed = elapsed
+            .checked_div(self.step_duration)?
+            .checked_mul(self.step_duration)?;
+        interpolate(start_price, floor_price, stepped_elapsed, duration)
+    }
+
+    fn validate(&self) -> Result<(), AuctionError> {
+        if self.step_duration <= 0 {
+            Err(AuctionError::InvalidPriceCurve)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn pack_params(&self, output: &mut [u8; CURVE_PARAMS_LEN]) {
+        let (step_duration, padding) = mut_array_refs![output, 8, 8];
+        *step_duration = self.step_duration.to_le_bytes();
+        *padding = [0; 8];
+    }
+}
+
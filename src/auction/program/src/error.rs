@@ -65,6 +65,173 @@ pub enum AuctionError {
     #[error("The auction was canceled")]
     Canceled,
 
+    /// The pool token account does not match the auction
+    #[error("Pool token account incorrect")]
+    IncorrectPoolAccount,
+
+    /// The bid record is not the program address for the auction and bidder
+    #[error("Bid record address or contents do not match the auction and bidder")]
+    InvalidBidRecord,
+
+    /// The bid is not high enough to enter the winning set
+    #[error("The bid is not high enough to enter the winning set")]
+    BidTooLow,
+
+    /// The number of lots is zero or above the supported maximum
+    #[error("The number of winners must be between one and the supported maximum")]
+    InvalidWinnerCount,
+
+    /// The auctioned lot amount is zero
+    #[error("The auctioned lot amount must be non zero")]
+    EmptyLot,
+
+    /// The auction has not reached its end timestamp
+    #[error("The auction has not ended yet")]
+    NotEnded,
+
+    /// The auction was already settled
+    #[error("The auction was already settled")]
+    AlreadySettled,
+
+    /// The bid is in the winning set of a live or settled auction
+    #[error("A winning bid cannot be refunded unless the auction is canceled")]
+    BidStillWinning,
+
+    /// The auction authority is not the expected key or did not sign
+    #[error("The auction authority is missing or did not sign")]
+    InvalidAuthority,
+
+    /// The reserve price does not match its commitment
+    #[error("The reserve price is set together with a commitment or does not match it")]
+    InvalidReserve,
+
+    /// The total bid is below the opening price of the auction
+    #[error("The bid is below the starting price")]
+    BidBelowStartingPrice,
+
+    /// The total bid does not beat the current bid by the minimum increment
+    #[error("The bid does not beat the current bid by the minimum increment")]
+    BidIncrementTooSmall,
+
+    /// The soft close window or durations are negative
+    #[error("The soft close window and durations must not be negative")]
+    InvalidSoftClose,
+
+    /// The Dutch auction price range or curve parameters are invalid
+    #[error("The Dutch auction price range or curve parameters are invalid")]
+    InvalidPriceCurve,
+
+    /// The bidder already holds a winning bid
+    #[error("The bidder already holds a winning bid")]
+    AlreadyWinning,
+
+    /// The instruction does not apply to the format of the auction
+    #[error("The instruction is not supported by this auction type")]
+    WrongAuctionType,
+
+    /// The instruction is outside its phase, or the phases are out of order
+    #[error("The instruction is not allowed in the current phase of the auction")]
+    InvalidPhase,
+
+    /// The sealed bid was already committed, or the revealed bid does not
+    /// match the commitment
+    #[error("The bid commitment is missing, already made or does not match the revealed bid")]
+    InvalidCommitment,
+
+    /// The deposit of a sealed bid is below the auction minimum
+    #[error("The deposit is below the minimum deposit of the auction")]
+    DepositTooLow,
+
+    /// The sealed bid was never revealed, its deposit is forfeited
+    #[error("An unrevealed sealed bid cannot be refunded")]
+    BidNotRevealed,
+
+    /// The quantity of a multi-unit bid is zero or above the lot amount
+    #[error("The bid quantity must be between one and the lot amount")]
+    InvalidQuantity,
+
+    /// The bidder already has a bid in the multi-unit auction
+    #[error("The bidder already placed a bid in this auction")]
+    DuplicateBid,
+
+    /// The bid is not linked at its place in clearing order, or is not the
+    /// next one to clear
+    #[error("Bids are linked and cleared by unit price highest first, then by bid time")]
+    InvalidClearingOrder,
+
+    /// Not every bid of the multi-unit auction was processed by the crank
+    #[error("The clearing of the bids is not complete")]
+    ClearingIncomplete,
+
+    /// The auction was not settled yet
+    #[error("The auction has not been settled yet")]
+    NotSettled,
+
+    /// The buy-it-now price is set on an auction that does not support it,
+    /// one with a hidden reserve included, or is below the starting or
+    /// reserve price
+    #[error("The buy-it-now price needs a single-lot English auction without a hidden reserve and must cover the starting and reserve prices")]
+    InvalidBuyNowPrice,
+
+    /// Another bidder already bid at least as much as the buy-it-now bid
+    #[error("A bid at or above the buy-it-now price already exists")]
+    BuyNowUnavailable,
+
+    /// The candle window is empty, longer than the auction or combined with
+    /// a soft close
+    #[error("The candle window must fit in the auction and excludes a soft close")]
+    InvalidCandleWindow,
+
+    /// The bid record of a candle auction holds no room for another bid
+    #[error("The bidder placed too many bids in the candle window")]
+    TooManyBids,
+
+    /// The retraction policy sets a penalty it does not use, a penalty above
+    /// the whole bid, or applies to an auction that is not English
+    #[error("The bid retraction policy or penalty is invalid")]
+    InvalidRetractPolicy,
+
+    /// The policy of the auction does not allow retracting the bid
+    #[error("The auction does not allow retracting this bid")]
+    RetractionForbidden,
+
+    /// The auction is gated and the allowlist proof is missing or invalid
+    #[error("The bidder is not on the allowlist of the auction")]
+    NotAllowlisted,
+
+    /// The total escrowed by the bidder is above its allowlist cap
+    #[error("The bid exceeds the cap of the bidder on the allowlist")]
+    AllowlistCapExceeded,
+
+    /// The total escrowed by the wallet is above the per-wallet bid cap
+    #[error("The bid exceeds the most a single wallet can bid in this auction")]
+    WalletBidCapExceeded,
+
+    /// The wallet bids for more units than the per-wallet unit cap
+    #[error("The bid exceeds the most units a single wallet can win in this auction")]
+    WalletUnitCapExceeded,
+
+    /// The program config account is not the config program address, or
+    /// is required and not initialized
+    #[error("The program config account is invalid or not initialized")]
+    InvalidProgramConfig,
+
+    /// The protocol owner paused the creation of auctions
+    #[error("The program is paused")]
+    ProgramPaused,
+
+    /// The auction account is of an older version and must be migrated
+    #[error("The auction must be migrated to the latest version first")]
+    OutdatedAuction,
+
+    /// The program data account is not the one of this program, or its
+    /// upgrade authority did not sign
+    #[error("The program upgrade authority is missing or did not sign")]
+    InvalidUpgradeAuthority,
+
+    /// Lots of a Dutch auction were already bought, those sales are final
+    #[error("A Dutch auction with bought lots cannot be canceled")]
+    LotsSold,
 
 }
 impl From<AuctionError> for ProgramError {
@@ -1,15 +1,17 @@
 //! Program fees
 
-use crate::error::AuctionError;
+use crate::{error::AuctionError, rules::BASIS_POINTS, state::ProgramConfig};
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use solana_program::{
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
 };
-use std::convert::TryFrom;
+use std::{cmp::Ordering, convert::TryFrom};
 
 /// Encapsulates all fee information and calculations for swap operations
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
 pub struct AuctionFees {
     /// At farm creation, if the LP token is not a B2B_token-CRP composition, user B will be subject to an additional fee of 500 CRP;
     pub auction_fee_numerator: u64,
@@ -49,23 +51,45 @@ fn validate_fraction(numerator: u64, denominator: u64) -> Result<(), AuctionErro
 }
 
 impl AuctionFees {
-    /// Calculate the withdraw fee in pool tokens
+    /// Calculate the auction fee in pool tokens
     pub fn auction_fee(&self, pool_tokens: u128) -> Option<u128> {
         calculate_fee(
             pool_tokens,
-            u128::try_from(self.auction_fee_numerator).ok()?,
-            u128::try_from(self.auction_fee_denominator).ok()?,
+            u128::from(self.auction_fee_numerator),
+            u128::from(self.auction_fee_denominator),
         )
     }
 
-    /// Validate that the fees are reasonable
-    pub fn validate(&self) -> Result<(), AuctionError> {
+    /// Validate that the fees are reasonable, and within the bounds of the
+    /// program config when there is one
+    pub fn validate(&self, config: Option<&ProgramConfig>) -> Result<(), AuctionError> {
         validate_fraction(
             self.auction_fee_numerator,
             self.auction_fee_denominator,
         )?;
+        if let Some(config) = config {
+            if self.cmp_fraction(&config.min_fees) == Ordering::Less
+                || self.cmp_fraction(&config.max_fees) == Ordering::Greater
+            {
+                return Err(AuctionError::InvalidFee);
+            }
+        }
         Ok(())
     }
+
+    /// Compare the fee fractions, a zero denominator being no fee
+    pub fn cmp_fraction(&self, other: &AuctionFees) -> Ordering {
+        let fraction = |fees: &AuctionFees| match fees.auction_fee_denominator {
+            0 => (0, 1),
+            denominator => (
+                u128::from(fees.auction_fee_numerator),
+                u128::from(denominator),
+            ),
+        };
+        let (numerator, denominator) = fraction(self);
+        let (other_numerator, other_denominator) = fraction(other);
+        (numerator * other_denominator).cmp(&(other_numerator * denominator))
+    }
 }
 
 /// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
@@ -77,9 +101,9 @@ impl IsInitialized for AuctionFees {
 
 impl Sealed for AuctionFees {}
 impl Pack for AuctionFees {
-    const LEN: usize = 64;
+    const LEN: usize = 2 * 8;
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, 16];
+        let output = array_mut_ref![output, 0, AuctionFees::LEN];
         let (
             auction_fee_numerator,
             auction_fee_denominator,
@@ -89,7 +113,7 @@ impl Pack for AuctionFees {
     }
 
     fn unpack_from_slice(input: &[u8]) -> Result<AuctionFees, ProgramError> {
-        let input = array_ref![input, 0, 16];
+        let input = array_ref![input, 0, AuctionFees::LEN];
         #[allow(clippy::ptr_offset_with_cast)]
         let (
             auction_fee_numerator,
@@ -101,3 +125,212 @@ impl Pack for AuctionFees {
         })
     }
 }
+
+/// Share of the settlement proceeds paid to an account other than the seller
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct FeeShare {
+    /// Share of the proceeds in basis points, 0 when unused
+    pub bps: u16,
+    /// Token account of the pool mint receiving the share
+    pub account: Pubkey,
+}
+
+impl FeeShare {
+    /// Share of `proceeds`, rounded down
+    pub fn amount(&self, proceeds: u64) -> Option<u64> {
+        let amount = u128::from(proceeds).checked_mul(u128::from(self.bps))? / BASIS_POINTS;
+        u64::try_from(amount).ok()
+    }
+}
+
+/// Proceeds of a settlement split between the fee recipients and the seller
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProceedsSplit {
+    /// Auction fee, paid to the fee account
+    pub protocol_fee: u64,
+    /// Creator royalties
+    pub royalty: u64,
+    /// Marketplace fee
+    pub marketplace_fee: u64,
+    /// Everything left, paid to the seller
+    pub seller: u64,
+}
+
+/// Shares of the proceeds paid out besides the auction fee, which stays the
+/// protocol fee of the fee account
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct FeeSplits {
+    /// Creator royalties
+    pub royalty: FeeShare,
+    /// Marketplace fee
+    pub marketplace: FeeShare,
+}
+
+impl FeeSplits {
+    /// Shares with a non zero cut, in payout order
+    pub fn active_shares(&self) -> impl Iterator<Item = &FeeShare> {
+        vec![&self.royalty, &self.marketplace]
+            .into_iter()
+            .filter(|share| share.bps > 0)
+    }
+
+    /// Split `proceeds`. Every share rounds down and the seller receives the
+    /// rest, so the parts always add up to the proceeds.
+    pub fn split(&self, fees: &AuctionFees, proceeds: u64) -> Option<ProceedsSplit> {
+        let protocol_fee = u64::try_from(fees.auction_fee(u128::from(proceeds))?).ok()?;
+        let remaining = proceeds.checked_sub(protocol_fee)?;
+        // the minimum auction fee may leave less than the rounded shares
+        let royalty = self.royalty.amount(proceeds)?.min(remaining);
+        let remaining = remaining - royalty;
+        let marketplace_fee = self.marketplace.amount(proceeds)?.min(remaining);
+        Some(ProceedsSplit {
+            protocol_fee,
+            royalty,
+            marketplace_fee,
+            seller: remaining - marketplace_fee,
+        })
+    }
+
+    /// Validate that the auction fee and the shares take at most the whole
+    /// proceeds
+    pub fn validate(&self, fees: &AuctionFees) -> Result<(), AuctionError> {
+        let shares_bps = u128::from(self.royalty.bps) + u128::from(self.marketplace.bps);
+        let denominator = u128::from(fees.auction_fee_denominator);
+        let within_whole = if denominator == 0 {
+            shares_bps <= BASIS_POINTS
+        } else {
+            u128::from(fees.auction_fee_numerator) * BASIS_POINTS + shares_bps * denominator
+                <= denominator * BASIS_POINTS
+        };
+        if within_whole {
+            Ok(())
+        } else {
+            Err(AuctionError::InvalidFee)
+        }
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for FeeSplits {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Sealed for FeeSplits {}
+impl Pack for FeeSplits {
+    const LEN: usize = 68;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 68];
+        let (royalty_bps, royalty_account, marketplace_bps, marketplace_account) =
+            mut_array_refs![output, 2, 32, 2, 32];
+        *royalty_bps = self.royalty.bps.to_le_bytes();
+        royalty_account.copy_from_slice(self.royalty.account.as_ref());
+        *marketplace_bps = self.marketplace.bps.to_le_bytes();
+        marketplace_account.copy_from_slice(self.marketplace.account.as_ref());
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<FeeSplits, ProgramError> {
+        let input = array_ref![input, 0, 68];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (royalty_bps, royalty_account, marketplace_bps, marketplace_account) =
+            array_refs![input, 2, 32, 2, 32];
+        Ok(Self {
+            royalty: FeeShare {
+                bps: u16::from_le_bytes(*royalty_bps),
+                account: Pubkey::new_from_array(*royalty_account),
+            },
+            marketplace: FeeShare {
+                bps: u16::from_le_bytes(*marketplace_bps),
+                account: Pubkey::new_from_array(*marketplace_account),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::{auction_fees, fee_splits};
+    use proptest::prelude::*;
+
+    #[test]
+    fn proceeds_split_without_dust() {
+        let fees = AuctionFees {
+            auction_fee_numerator: 25,
+            auction_fee_denominator: 1_000,
+        };
+        let splits = FeeSplits {
+            royalty: FeeShare {
+                bps: 500,
+                account: Pubkey::new_unique(),
+            },
+            marketplace: FeeShare {
+                bps: 150,
+                account: Pubkey::new_unique(),
+            },
+        };
+        assert_eq!(splits.validate(&fees), Ok(()));
+        let split = splits.split(&fees, 9_999).unwrap();
+        assert_eq!(
+            split,
+            ProceedsSplit {
+                protocol_fee: 249,
+                royalty: 499,
+                marketplace_fee: 149,
+                seller: 9_102,
+            }
+        );
+        // the minimum auction fee leaves nothing for the shares
+        let split = splits.split(&fees, 1).unwrap();
+        assert_eq!(split.protocol_fee + split.royalty + split.marketplace_fee + split.seller, 1);
+
+        // 2.5% + 98% + 1.5% is more than the whole
+        let greedy = FeeSplits {
+            royalty: FeeShare {
+                bps: 9_800,
+                ..FeeShare::default()
+            },
+            marketplace: splits.marketplace.clone(),
+        };
+        assert_eq!(greedy.validate(&fees), Err(AuctionError::InvalidFee));
+        assert_eq!(greedy.validate(&AuctionFees::default()), Ok(()));
+    }
+
+    #[test]
+    fn fees_within_config_bounds() {
+        let fees = |auction_fee_numerator, auction_fee_denominator| AuctionFees {
+            auction_fee_numerator,
+            auction_fee_denominator,
+        };
+        let config = ProgramConfig {
+            min_fees: fees(1, 100),
+            max_fees: fees(5, 100),
+            ..ProgramConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(fees(10, 1_000).validate(Some(&config)), Ok(()));
+        assert_eq!(fees(1, 20).validate(Some(&config)), Ok(()));
+        assert_eq!(fees(0, 0).validate(Some(&config)), Err(AuctionError::InvalidFee));
+        assert_eq!(fees(51, 1_000).validate(Some(&config)), Err(AuctionError::InvalidFee));
+        assert_eq!(fees(0, 0).validate(None), Ok(()));
+        let crossed = ProgramConfig {
+            min_fees: fees(5, 100),
+            max_fees: fees(1, 100),
+            ..ProgramConfig::default()
+        };
+        assert_eq!(crossed.validate(), Err(AuctionError::InvalidFee));
+    }
+
+    proptest! {
+        #[test]
+        fn pack_round_trip(fees in auction_fees(), splits in fee_splits()) {
+            let mut packed = [0u8; AuctionFees::LEN];
+            Pack::pack_into_slice(&fees, &mut packed);
+            prop_assert_eq!(AuctionFees::unpack_from_slice(&packed).unwrap(), fees);
+            let mut packed = [0u8; FeeSplits::LEN];
+            Pack::pack_into_slice(&splits, &mut packed);
+            prop_assert_eq!(FeeSplits::unpack_from_slice(&packed).unwrap(), splits);
+        }
+    }
+}
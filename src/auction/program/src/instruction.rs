@@ -3,112 +3,554 @@
 #![allow(clippy::too_many_arguments)]
 
 use crate::{
-    fees::AuctionFees,
+    curve::base::DutchPrice,
+    fees::{AuctionFees, FeeSplits},
     error::AuctionError,
+    rules::{BidRules, RetractRules, SoftClose, WalletCaps},
+    state::{
+        find_config_address, find_migration_address, find_unwrap_address, AuctionType, PricingRule,
+    },
 };
 
+use borsh::{
+    schema::{Declaration, Definition},
+    BorshDeserialize, BorshSchema, BorshSerialize,
+};
 use solana_program::{
+    bpf_loader_upgradeable,
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     program_pack::Pack,
     pubkey::Pubkey,
     clock::UnixTimestamp,
+    system_program,
     sysvar,
 };
-use std::convert::TryInto;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 use std::mem::size_of;
 
 #[cfg(feature = "fuzz")]
 use arbitrary::Arbitrary;
 
-
-/// Defines which validator vote account is set during the SetPreferredValidator instruction
-#[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
-pub enum PreferredValidatorType {
-    /// set preffered validator for deposits
-    PlaceBid,
-
-    /// set preffered validator for withdraws
-    Withdraw,
-}
+/// Leading byte of a Borsh encoded instruction. The packed encoding keeps
+/// using the variant index as its tag, so both are accepted by `unpack`.
+pub const BORSH_INSTRUCTION_TAG: u8 = 255;
 
 /// Initialize instruction data
 #[repr(C)]
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
 pub struct InitializeData {
     /// auction fees
     pub fees: AuctionFees,
 
-    pub nonce: u8,
+    /// index of the auction among those of the seller for the lot mint,
+    /// seed of the auction program address
+    pub auction_index: u64,
 
     pub start_timestamp: UnixTimestamp,
 
-    pub end_timestamp: UnixTimestamp
+    pub end_timestamp: UnixTimestamp,
+
+    /// number of lots sold, the top `max_winners` bids win
+    pub max_winners: u8,
+
+    /// amount of the lot mint escrowed for sale, 1 for an NFT
+    pub lot_amount: u64,
+
+    /// lowest bid that can win, must be zero when the reserve is hidden
+    pub reserve_price: u64,
+
+    /// hash of the auction, seller, hidden reserve price and a salt, see
+    /// `state::hash_commitment`, revealed at settlement
+    pub reserve_commitment: Option<[u8; 32]>,
+
+    /// starting price and minimum increments of bids
+    pub bid_rules: BidRules,
+
+    /// extension of the end on late bids
+    pub soft_close: SoftClose,
+
+    /// format of the auction
+    pub auction_type: AuctionType,
+
+    /// descending price, used by Dutch auctions only
+    pub dutch_price: DutchPrice,
+
+    /// end of the reveal phase, used by sealed-bid auctions only
+    pub reveal_end_timestamp: UnixTimestamp,
+
+    /// lowest deposit escrowed with a sealed bid commitment
+    pub min_deposit: u64,
+
+    /// price the winners pay, second price is for sealed-bid auctions only
+    pub pricing_rule: PricingRule,
+
+    /// bid that ends and settles a single-lot English auction at once,
+    /// zero when disabled, not allowed with a hidden reserve
+    pub buy_now_price: u64,
+
+    /// seconds before the end in which a candle auction may close
+    pub candle_window: UnixTimestamp,
+
+    /// retraction of bids before the end, English auctions only
+    pub retract_rules: RetractRules,
+
+    /// root of the Merkle tree of bidders allowed to bid, see
+    /// `state::allowlist_leaf`, anyone can bid when unset
+    pub allowlist_root: Option<[u8; 32]>,
+
+    /// limits on what a single wallet can bid and win
+    pub wallet_caps: WalletCaps,
+
+    /// royalty and marketplace shares of the proceeds
+    pub fee_splits: FeeSplits,
+}
+
+/// Proof that a bidder is on the allowlist of a gated auction
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct AllowlistProof {
+    /// most the bidder may escrow, zero for no cap, as in the leaf
+    pub cap: u64,
+    /// sibling hashes from the leaf up to the root
+    pub proof: Vec<[u8; 32]>,
 }
 /// PlaceBid instruction data
 #[cfg_attr(feature = "fuzz", derive(Arbitrary))]
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
 pub struct PlaceBid {
     
     /// Bid amount to deposit, prevents excessive slippage
     pub bid_amount: u64,
+
+    /// allowlist proof of the bidder, required in a gated auction
+    pub allowlist_proof: Option<AllowlistProof>,
 }
 
-/// Withdraw instruction data
+/// Cancel instruction data
 #[cfg_attr(feature = "fuzz", derive(Arbitrary))]
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
-pub struct Withdraw {
-    /// withdraw winning bid amount
-    pub bid_amount: u64,
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct Cancel {
+    /// must be non zero, a canceled auction cannot be reopened
+    pub canceled: u8,
 }
-/// Withdraw instruction data
+
+/// Reserve price and salt opening a hidden reserve commitment
 #[cfg_attr(feature = "fuzz", derive(Arbitrary))]
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq)]
-pub struct Cancel {
-    /// withdraw winning bid amount
-    pub canceled: u8,
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct ReserveReveal {
+    /// hidden reserve price
+    pub reserve_price: u64,
+    /// salt the reserve price was committed with
+    pub salt: [u8; 32],
+}
+
+/// Settle instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct Settle {
+    /// opening of the reserve commitment, required when the reserve is hidden
+    pub reserve_reveal: Option<ReserveReveal>,
+}
+
+/// CommitBid instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct CommitBid {
+    /// hash of the auction, bidder, bid amount and a salt, see
+    /// `state::hash_commitment`
+    pub commitment: [u8; 32],
+    /// amount escrowed with the commitment
+    pub deposit: u64,
+    /// allowlist proof of the bidder, required in a gated auction
+    pub allowlist_proof: Option<AllowlistProof>,
+}
+
+/// RevealBid instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct RevealBid {
+    /// committed bid amount
+    pub amount: u64,
+    /// salt the bid amount was committed with
+    pub salt: [u8; 32],
+}
+
+/// PlaceUnitBid instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct PlaceUnitBid {
+    /// units of the lot bid for
+    pub quantity: u64,
+    /// highest price paid per unit
+    pub unit_price: u64,
+    /// allowlist proof of the bidder, required in a gated auction
+    pub allowlist_proof: Option<AllowlistProof>,
+}
+
+/// SetAllowlist instruction data
+#[cfg_attr(feature = "fuzz", derive(Arbitrary))]
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct SetAllowlist {
+    /// new root of the allowlist Merkle tree, none to open the auction to
+    /// anyone
+    pub allowlist_root: Option<[u8; 32]>,
+}
+
+/// InitializeConfig and UpdateConfig instruction data
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct ConfigData {
+    /// protocol owner, allowed to update the config
+    pub owner: Pubkey,
+    /// lowest auction fee of new auctions
+    pub min_fees: AuctionFees,
+    /// highest auction fee of new auctions
+    pub max_fees: AuctionFees,
+    /// owner the fee account of new auctions must have, any when unset
+    pub fee_owner: Option<Pubkey>,
+    /// stop the creation of new auctions
+    pub paused: bool,
 }
 
 /// Instructions supported by the auction program
 #[repr(C)]
-#[derive( Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum AuctionInstruction {
     ///   Initializes a new Auction
     ///
-    ///   0. `[writable, signer]` New Auction to create.
+    ///   0. `[writable]` New Auction to create, program address derived from
+    ///      seller, lot mint and auction index, see `state::find_auction_address`
     ///   1. `[]` owner token Account. Must be non zero
-    ///   2. `[]` Fee Token Account to deposit and withdraw fees.
-    ///   3. `[]` Pool Token Account to deposit bids
-    ///   4. '[]` Token program id
+    ///   2. `[writable]` Pool Token Account to deposit bids, owned by the auction program authority
+    ///   3. `[]` Fee Token Account to deposit and withdraw fees.
+    ///   4. '[]` Token program id, the SPL token program
+    ///   5. `[writable]` Seller token account holding the lot
+    ///   6. `[writable]` Lot vault token account, owned by the auction program authority
+    ///   7. `[writable, signer]` Seller, transfer authority of the lot and authority of
+    ///      the auction, pays for the auction account
+    ///   8. `[]` Program config, see `state::find_config_address`
+    ///   9. `[]` System program
+    ///   10. `[]` Rent sysvar
+    ///   11. ..11+S `[]` Royalty then marketplace token accounts, for the S shares set
+    ///
+    ///   A pool of the native mint takes bids in SOL. The owner account is
+    ///   then the seller wallet, and the seller funds the auction program
+    ///   authority for the account that unwraps the payouts:
+    ///   11+S. `[writable]` Auction program authority
     Initialize(InitializeData),
 
-    ///   deposit bid amount
+    ///   deposit bid amount. In a Dutch auction, buy one lot at the current
+    ///   price, with the bid amount as the highest price accepted. A total
+    ///   bid at or above the buy-it-now price ends and settles the auction at
+    ///   that price, only what it takes to reach it is deposited, and needs
+    ///   the trailing accounts.
     ///
-    ///   0. `[]` Auction
+    ///   0. `[writable]` Auction
     ///   1. `[writable]` token Base Account to deposit into.
     ///   2. `[writable]` Pool Account to deposit the tokens
     ///   3. '[]` Token program id
-    ///   4. '[]' user_transfer_authority
+    ///   4. '[signer]' user_transfer_authority
+    ///   5. `[]` Clock sysvar
+    ///   6. `[writable, signer]` Bidder, pays for the bid record
+    ///   7. `[writable]` Bid record, program address derived from auction and bidder
+    ///   8. `[]` System program
+    ///   9. `[]` Rent sysvar
+    ///   10. `[writable]` Auction program authority, for buy-it-now
+    ///   11. `[writable]` owner token Account, receives the proceeds
+    ///   12. `[writable]` Fee account, receives the auction fee
+    ///   13. `[writable]` Lot vault token account
+    ///   14. `[writable]` Seller lot token account
+    ///   15. `[writable]` Lot token account of the bidder
+    ///   16. ..16+S `[writable]` Royalty then marketplace token accounts, for the S shares set
+    ///
+    ///   A gated auction only takes bids with a proof that the bidder is on
+    ///   its allowlist, and up to the cap in the proof. The per-wallet caps of
+    ///   the auction bound the total a bidder escrows and the units it wins.
+    ///
+    ///   In a native SOL auction the bidder pays lamports directly: account 1
+    ///   is the `[writable, signer]` wallet of the bidder, and the proceeds go
+    ///   to the seller wallet. A buy-it-now bid then ends with the unwrap
+    ///   accounts listed for `Settle`.
     PlaceBid(PlaceBid),
 
-    ///   Withdraw winning bid
+    ///   Cancel an auction before it is over, and before any lot of a Dutch
+    ///   auction is bought. Canceling cannot be undone, the lot goes back to
+    ///   the seller and every bid becomes refundable.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[signer]` Auction authority
+    ///   2. `[]` Auction program authority
+    ///   3. `[writable]` Lot vault token account
+    ///   4. `[writable]` Seller token account to return the lot to
+    ///   5. `[]` Token program id
+    ///   6. `[]` Clock sysvar
+    Cancel(Cancel),
+
+    ///   Settle an ended auction: deliver the lot to the winners and the
+    ///   winning bids, less the auction fee and the royalty and marketplace
+    ///   shares, to the seller. Winning bids below
+    ///   the reserve price do not buy, their lots go back to the seller and
+    ///   the bids become refundable. Deposits of sealed bids that were never
+    ///   revealed are paid to the seller with the proceeds. Under the
+    ///   second-price rule every winner pays the second price and the rest of
+    ///   their escrow is refunded.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[signer]` Auction authority
+    ///   2. `[writable]` Auction program authority
+    ///   3. `[writable]` Pool token account
+    ///   4. `[writable]` owner token Account, receives the proceeds
+    ///   5. `[writable]` Fee account, receives the auction fee
+    ///   6. `[writable]` Lot vault token account
+    ///   7. `[writable]` Seller lot token account, receives unsold lots
+    ///   8. `[]` Token program id
+    ///   9. `[]` Clock sysvar
+    ///   10. ..10+N `[writable]` Lot token accounts of the N winners, highest bid first
+    ///
+    ///   Under the second-price rule each winner passes three accounts instead:
+    ///   10+3i. `[writable]` Lot token account of the winner
+    ///   11+3i. `[writable]` Bid record of the winner
+    ///   12+3i. `[writable]` token Account of the winner to refund
+    ///
+    ///   The royalty then marketplace token accounts follow the winners, for
+    ///   the shares set.
+    ///
+    ///   A native SOL auction pays the seller and the refunds in lamports, to
+    ///   the wallets instead of token accounts, and makes the auction program
+    ///   authority writable. Four accounts follow the fee shares:
+    ///   0. `[writable]` Unwrap account, program address derived from the auction
+    ///   1. `[]` Native mint
+    ///   2. `[]` System program
+    ///   3. `[]` Rent sysvar
+    Settle(Settle),
+
+    ///   Return the escrowed amount of a bidder that is no longer winning,
+    ///   or of any bidder once the auction is canceled. A winner of a settled
+    ///   auction gets back what was escrowed above the winning bid, and an
+    ///   unrevealed sealed bid is not refunded. A no-op when the bid was
+    ///   already refunded.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[writable]` Auction program authority
+    ///   2. `[writable]` Pool token account
+    ///   3. `[writable]` Bid record
+    ///   4. `[writable]` token Account of the bidder to credit
+    ///   5. `[]` Token program id
+    ///
+    ///   A native SOL auction credits the bidder wallet as account 4, and
+    ///   ends with the unwrap accounts listed for `Settle`.
+    ClaimRefund,
+
+    ///   Commit to a sealed bid before the end of a sealed-bid auction,
+    ///   escrowing a deposit. A bid that is not revealed before the end of the
+    ///   reveal phase forfeits its deposit to the seller.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[writable]` token Base Account to deposit from.
+    ///   2. `[writable]` Pool Account to deposit the tokens
+    ///   3. '[]` Token program id
+    ///   4. '[signer]' user_transfer_authority
+    ///   5. `[]` Clock sysvar
+    ///   6. `[writable, signer]` Bidder, pays for the bid record
+    ///   7. `[writable]` Bid record, program address derived from auction and bidder
+    ///   8. `[]` System program
+    ///   9. `[]` Rent sysvar
+    ///
+    ///   A gated auction needs an allowlist proof, as for `PlaceBid`.
+    CommitBid(CommitBid),
+
+    ///   Reveal a sealed bid during the reveal phase, topping the escrow up to
+    ///   the bid amount. The bid enters the winning set if it is high enough.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[writable]` token Base Account to top up from.
+    ///   2. `[writable]` Pool Account to deposit the tokens
+    ///   3. '[]` Token program id
+    ///   4. '[signer]' user_transfer_authority
+    ///   5. `[]` Clock sysvar
+    ///   6. `[signer]` Bidder
+    ///   7. `[writable]` Bid record
+    ///   8. `[]` System program
+    RevealBid(RevealBid),
+
+    ///   Bid for a quantity of units in a multi-unit auction, escrowing the
+    ///   quantity times the unit price. One bid per bidder.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[writable]` token Base Account to deposit from.
+    ///   2. `[writable]` Pool Account to deposit the tokens
+    ///   3. '[]` Token program id
+    ///   4. '[signer]' user_transfer_authority
+    ///   5. `[]` Clock sysvar
+    ///   6. `[writable, signer]` Bidder, pays for the bid record
+    ///   7. `[writable]` Bid record, program address derived from auction and bidder
+    ///   8. `[]` System program
+    ///   9. `[]` Rent sysvar
+    ///   10. `[writable]` Bid record the new bid is linked after in clearing
+    ///       order, or the auction when the new bid clears first
+    ///   11. `[]` Bid record currently linked after it, omitted when there is
+    ///       none
+    ///
+    ///   A gated auction needs an allowlist proof, as for `PlaceBid`.
+    PlaceUnitBid(PlaceUnitBid),
+
+    ///   Crank the clearing of an ended multi-unit auction. Bids are linked
+    ///   by unit price highest first, then by bid time, then by bidder when
+    ///   they are placed, and the bid records must follow that list from the
+    ///   clearing head of the auction. Anyone can crank, in as many
+    ///   transactions as needed.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[]` Clock sysvar
+    ///   2. ..2+N `[writable]` Bid records, in clearing order
+    ClearBids,
+
+    ///   Deliver the filled units of a settled multi-unit auction to the
+    ///   bidder and refund the escrow above the clearing price.
     ///
     ///   0. `[]` Auction
-    ///   1. `[writable]` SOURCE Pool token account
-    ///   3. `[writable]` token user Account to credit.
-    ///   4. `[writable]` Fee account, to receive withdrawal fees
-    ///   5 '[]` Token program id
-    ///   6. '[]' user_transfer_authority
-    Withdraw(Withdraw),
+    ///   1. `[writable]` Auction program authority
+    ///   2. `[writable]` Pool token account
+    ///   3. `[writable]` Lot vault token account
+    ///   4. `[writable]` Bid record
+    ///   5. `[writable]` token Account of the bidder to refund
+    ///   6. `[writable]` Lot token account of the bidder
+    ///   7. `[]` Token program id
+    ///
+    ///   A native SOL auction refunds the bidder wallet as account 5, and
+    ///   ends with the unwrap accounts listed for `Settle`.
+    ClaimFill,
+
+    ///   Resolve an ended candle auction. The first call fixes the slot
+    ///   after it, whose hash picks the close inside the candle window, and
+    ///   counts no bids. Once that slot has passed, the next call draws the
+    ///   close from the hash of the first slot at or after it, and every
+    ///   call counts the totals the given bidders had at the close into the
+    ///   winning set. A hash that left the SlotHashes history is replaced by
+    ///   that of a new slot, fixed the same way. Anyone can crank, in as many
+    ///   transactions as needed, until every bid record was counted.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[]` Clock sysvar
+    ///   2. `[]` SlotHashes sysvar
+    ///   3. ..3+N `[writable]` Bid records
+    ResolveCandle,
+
+    ///   Retract the bid of a live English auction and refund it, less the
+    ///   penalty set by the retraction policy of the auction. The highest
+    ///   bid on standby takes the place of a retracted winning bid.
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[writable]` Auction program authority
+    ///   2. `[writable]` Pool token account
+    ///   3. `[writable]` Bid record
+    ///   4. `[writable]` token Account of the bidder to credit
+    ///   5. `[signer]` Bidder
+    ///   6. `[]` Token program id
+    ///   7. `[]` Clock sysvar
+    ///   8. `[writable]` owner token Account or fee account, receives the
+    ///      penalty, only under the penalty policy
+    ///
+    ///   A native SOL auction credits the bidder wallet as account 4, pays a
+    ///   penalty for the seller to the seller wallet, and ends with the unwrap
+    ///   accounts listed for `Settle`.
+    RetractBid,
+
+    ///   Replace the allowlist root of an auction before it starts
+    ///
+    ///   0. `[writable]` Auction
+    ///   1. `[signer]` Auction authority
+    ///   2. `[]` Clock sysvar
+    SetAllowlist(SetAllowlist),
+
+    ///   Create the program config constraining new auctions. It can be
+    ///   created once, by the upgrade authority of the program, so it is
+    ///   initialized along with the deployment.
+    ///
+    ///   0. `[writable]` Program config, see `state::find_config_address`
+    ///   1. `[writable, signer]` Payer of the config account
+    ///   2. `[]` System program
+    ///   3. `[]` Rent sysvar
+    ///   4. `[]` Program data account of the program, owned by the
+    ///      upgradeable BPF loader
+    ///   5. `[signer]` Upgrade authority of the program
+    InitializeConfig(ConfigData),
+
+    ///   Replace the program config, the owner included
+    ///
+    ///   0. `[writable]` Program config
+    ///   1. `[signer]` Protocol owner
+    UpdateConfig(ConfigData),
+
+    ///   Rewrite an AuctionV1 account as the latest version, in two
+    ///   transactions since the account cannot grow in place. The first
+    ///   copies the auction to the migration account and closes the auction
+    ///   account, the second recreates it at the latest size from the copy.
+    ///   Both are signed by the keypair the auction was created with, the
+    ///   owner of its owner token account becomes the authority. The payer
+    ///   funds the new accounts and gets back the rent of the closed ones.
+    ///
+    ///   0. `[writable, signer]` Auction
+    ///   1. `[writable, signer]` Payer
+    ///   2. `[writable]` Migration account, see `state::find_migration_address`
+    ///   3. `[]` Owner token account recorded in the auction
+    ///   4. `[]` System program
+    ///   5. `[]` Rent sysvar
+    MigrateAuction,
+}
+
+/// Written out rather than derived so that each variant is described by its
+/// payload type instead of a wrapper struct
+impl BorshSchema for AuctionInstruction {
+    fn add_definitions_recursively(definitions: &mut HashMap<Declaration, Definition>) {
+        InitializeData::add_definitions_recursively(definitions);
+        PlaceBid::add_definitions_recursively(definitions);
+        Cancel::add_definitions_recursively(definitions);
+        Settle::add_definitions_recursively(definitions);
+        CommitBid::add_definitions_recursively(definitions);
+        RevealBid::add_definitions_recursively(definitions);
+        PlaceUnitBid::add_definitions_recursively(definitions);
+        SetAllowlist::add_definitions_recursively(definitions);
+        ConfigData::add_definitions_recursively(definitions);
+        let variants = [
+            ("Initialize", InitializeData::declaration()),
+            ("PlaceBid", PlaceBid::declaration()),
+            ("Cancel", Cancel::declaration()),
+            ("Settle", Settle::declaration()),
+            ("ClaimRefund", <()>::declaration()),
+            ("CommitBid", CommitBid::declaration()),
+            ("RevealBid", RevealBid::declaration()),
+            ("PlaceUnitBid", PlaceUnitBid::declaration()),
+            ("ClearBids", <()>::declaration()),
+            ("ClaimFill", <()>::declaration()),
+            ("ResolveCandle", <()>::declaration()),
+            ("RetractBid", <()>::declaration()),
+            ("SetAllowlist", SetAllowlist::declaration()),
+            ("InitializeConfig", ConfigData::declaration()),
+            ("UpdateConfig", ConfigData::declaration()),
+            ("MigrateAuction", <()>::declaration()),
+        ]
+        .iter()
+        .map(|(name, declaration)| (name.to_string(), declaration.clone()))
+        .collect();
+        Self::add_definition(Self::declaration(), Definition::Enum { variants }, definitions);
+    }
 
-    /// Cancel auction
-    /// 
-    /// 0.'[]' Auction
-    Cancel(Cancel)
+    fn declaration() -> Declaration {
+        "AuctionInstruction".to_string()
+    }
 }
 
 
@@ -117,45 +559,150 @@ impl AuctionInstruction {
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         let (&tag, _rest) = input.split_first().ok_or(AuctionError::InvalidInstruction)?;
         Ok(match tag {
-            0 => {
-                
-                if _rest.len() >= AuctionFees::LEN {
-                    let (fees, _rest) = _rest.split_at(AuctionFees::LEN);
-                    let fees = AuctionFees::unpack_unchecked(fees)?;
-
-                    let (&nonce, _rest) = _rest.split_first().ok_or(AuctionError::InvalidInstruction)?;
-
-                    let (start_timestamp, _rest) = Self::unpack_i64(_rest)?;
-                    let (end_timestamp, _rest) = Self::unpack_i64(_rest)?;
-
-                    Self::Initialize(InitializeData {
-                        fees,
-                        nonce,
-                        start_timestamp,
-                        end_timestamp,
-                    })
-                } else {
+            BORSH_INSTRUCTION_TAG => Self::try_from_slice(_rest)
+                .map_err(|_| AuctionError::InvalidInstruction)?,
+            0 if _rest.len() >= AuctionFees::LEN => {
+                let (fees, _rest) = _rest.split_at(AuctionFees::LEN);
+                let fees = AuctionFees::unpack_unchecked(fees)?;
+
+                let (auction_index, _rest) = Self::unpack_u64(_rest)?;
+
+                let (start_timestamp, _rest) = Self::unpack_i64(_rest)?;
+                let (end_timestamp, _rest) = Self::unpack_i64(_rest)?;
+                let (&max_winners, _rest) = _rest.split_first().ok_or(AuctionError::InvalidInstruction)?;
+                let (lot_amount, _rest) = Self::unpack_u64(_rest)?;
+                let (reserve_price, _rest) = Self::unpack_u64(_rest)?;
+                let (reserve_commitment, _rest) = Self::unpack_hash_option(_rest)?;
+                if _rest.len() < BidRules::LEN {
+                    return Err(AuctionError::InvalidInstruction.into());
+                }
+                let (bid_rules, _rest) = _rest.split_at(BidRules::LEN);
+                let bid_rules = BidRules::unpack_unchecked(bid_rules)?;
+                if _rest.len() < SoftClose::LEN {
                     return Err(AuctionError::InvalidInstruction.into());
                 }
+                let (soft_close, _rest) = _rest.split_at(SoftClose::LEN);
+                let soft_close = SoftClose::unpack_unchecked(soft_close)?;
+                let (&auction_type, _rest) = _rest.split_first().ok_or(AuctionError::InvalidInstruction)?;
+                let auction_type = AuctionType::try_from(auction_type)
+                    .map_err(|_| AuctionError::InvalidInstruction)?;
+                if _rest.len() < DutchPrice::LEN {
+                    return Err(AuctionError::InvalidInstruction.into());
+                }
+                let (dutch_price, _rest) = _rest.split_at(DutchPrice::LEN);
+                let dutch_price = DutchPrice::unpack_unchecked(dutch_price)
+                    .map_err(|_| AuctionError::InvalidInstruction)?;
+                let (reveal_end_timestamp, _rest) = Self::unpack_i64(_rest)?;
+                let (min_deposit, _rest) = Self::unpack_u64(_rest)?;
+                let (&pricing_rule, _rest) = _rest.split_first().ok_or(AuctionError::InvalidInstruction)?;
+                let pricing_rule = PricingRule::try_from(pricing_rule)
+                    .map_err(|_| AuctionError::InvalidInstruction)?;
+                let (buy_now_price, _rest) = Self::unpack_u64(_rest)?;
+                let (candle_window, _rest) = Self::unpack_i64(_rest)?;
+                if _rest.len() < RetractRules::LEN {
+                    return Err(AuctionError::InvalidInstruction.into());
+                }
+                let (retract_rules, _rest) = _rest.split_at(RetractRules::LEN);
+                let retract_rules = RetractRules::unpack_unchecked(retract_rules)
+                    .map_err(|_| AuctionError::InvalidInstruction)?;
+                let (allowlist_root, _rest) = Self::unpack_hash_option(_rest)?;
+                if _rest.len() < WalletCaps::LEN {
+                    return Err(AuctionError::InvalidInstruction.into());
+                }
+                let (wallet_caps, _rest) = _rest.split_at(WalletCaps::LEN);
+                let wallet_caps = WalletCaps::unpack_unchecked(wallet_caps)?;
+                if _rest.len() < FeeSplits::LEN {
+                    return Err(AuctionError::InvalidInstruction.into());
+                }
+                let (fee_splits, _rest) = _rest.split_at(FeeSplits::LEN);
+                let fee_splits = FeeSplits::unpack_unchecked(fee_splits)?;
+
+                Self::Initialize(InitializeData {
+                    fees,
+                    auction_index,
+                    start_timestamp,
+                    end_timestamp,
+                    max_winners,
+                    lot_amount,
+                    reserve_price,
+                    reserve_commitment,
+                    bid_rules,
+                    soft_close,
+                    auction_type,
+                    dutch_price,
+                    reveal_end_timestamp,
+                    min_deposit,
+                    pricing_rule,
+                    buy_now_price,
+                    candle_window,
+                    retract_rules,
+                    allowlist_root,
+                    wallet_caps,
+                    fee_splits,
+                })
             }
             1 => {
                 let (bid_amount, _rest) = Self::unpack_u64(_rest)?;
+                let allowlist_proof = Self::unpack_allowlist_proof(_rest)?;
                 Self::PlaceBid(PlaceBid {
                     bid_amount,
+                    allowlist_proof,
                 })
             }
-            2 => {
-                let (bid_amount, _rest) = Self::unpack_u64(_rest)?;
-                Self::Withdraw(Withdraw {
-                    bid_amount,
-                })
-            }
+            // 2 was Withdraw, the tag is not reused
             3 => {
                 let (&canceled, _rest) = _rest.split_first().ok_or(AuctionError::InvalidInstruction)?;
                 Self::Cancel(Cancel {
                     canceled,
                 })
             }
+            4 => {
+                let reserve_reveal = if _rest.is_empty() {
+                    None
+                } else {
+                    let (reserve_price, _rest) = Self::unpack_u64(_rest)?;
+                    let (salt, _rest) = Self::unpack_hash(_rest)?;
+                    Some(ReserveReveal { reserve_price, salt })
+                };
+                Self::Settle(Settle { reserve_reveal })
+            }
+            5 => Self::ClaimRefund,
+            6 => {
+                let (commitment, _rest) = Self::unpack_hash(_rest)?;
+                let (deposit, _rest) = Self::unpack_u64(_rest)?;
+                let allowlist_proof = Self::unpack_allowlist_proof(_rest)?;
+                Self::CommitBid(CommitBid {
+                    commitment,
+                    deposit,
+                    allowlist_proof,
+                })
+            }
+            7 => {
+                let (amount, _rest) = Self::unpack_u64(_rest)?;
+                let (salt, _rest) = Self::unpack_hash(_rest)?;
+                Self::RevealBid(RevealBid { amount, salt })
+            }
+            8 => {
+                let (quantity, _rest) = Self::unpack_u64(_rest)?;
+                let (unit_price, _rest) = Self::unpack_u64(_rest)?;
+                let allowlist_proof = Self::unpack_allowlist_proof(_rest)?;
+                Self::PlaceUnitBid(PlaceUnitBid {
+                    quantity,
+                    unit_price,
+                    allowlist_proof,
+                })
+            }
+            9 => Self::ClearBids,
+            10 => Self::ClaimFill,
+            11 => Self::ResolveCandle,
+            12 => Self::RetractBid,
+            13 => {
+                let (allowlist_root, _rest) = Self::unpack_hash_option(_rest)?;
+                Self::SetAllowlist(SetAllowlist { allowlist_root })
+            }
+            14 => Self::InitializeConfig(Self::unpack_config_data(_rest)?),
+            15 => Self::UpdateConfig(Self::unpack_config_data(_rest)?),
+            16 => Self::MigrateAuction,
             
             _ => return Err(AuctionError::InvalidInstruction.into()),
         })
@@ -188,37 +735,166 @@ impl AuctionInstruction {
         }
     }
 
+    fn unpack_hash(input: &[u8]) -> Result<([u8; 32], &[u8]), ProgramError> {
+        if input.len() >= 32 {
+            let (hash, _rest) = input.split_at(32);
+            let hash = hash
+                .try_into()
+                .map_err(|_| AuctionError::InvalidInstruction)?;
+            Ok((hash, _rest))
+        } else {
+            Err(AuctionError::InvalidInstruction.into())
+        }
+    }
+    fn unpack_hash_option(input: &[u8]) -> Result<(Option<[u8; 32]>, &[u8]), ProgramError> {
+        let (&flag, _rest) = input.split_first().ok_or(AuctionError::InvalidInstruction)?;
+        match flag {
+            0 => Ok((None, _rest)),
+            1 => {
+                let (hash, _rest) = Self::unpack_hash(_rest)?;
+                Ok((Some(hash), _rest))
+            }
+            _ => Err(AuctionError::InvalidInstruction.into()),
+        }
+    }
+    fn unpack_config_data(input: &[u8]) -> Result<ConfigData, ProgramError> {
+        let (owner, rest) = Self::unpack_hash(input)?;
+        if rest.len() < 2 * AuctionFees::LEN {
+            return Err(AuctionError::InvalidInstruction.into());
+        }
+        let (min_fees, rest) = rest.split_at(AuctionFees::LEN);
+        let (max_fees, rest) = rest.split_at(AuctionFees::LEN);
+        let (fee_owner, rest) = Self::unpack_hash_option(rest)?;
+        let paused = match rest.first() {
+            Some(0) => false,
+            Some(1) => true,
+            _ => return Err(AuctionError::InvalidInstruction.into()),
+        };
+        Ok(ConfigData {
+            owner: Pubkey::new_from_array(owner),
+            min_fees: AuctionFees::unpack_from_slice(min_fees)?,
+            max_fees: AuctionFees::unpack_from_slice(max_fees)?,
+            fee_owner: fee_owner.map(Pubkey::new_from_array),
+            paused,
+        })
+    }
+    fn pack_config_data(config: &ConfigData, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(config.owner.as_ref());
+        let mut fees_slice = [0u8; AuctionFees::LEN];
+        config.min_fees.pack_into_slice(&mut fees_slice[..]);
+        buf.extend_from_slice(&fees_slice);
+        config.max_fees.pack_into_slice(&mut fees_slice[..]);
+        buf.extend_from_slice(&fees_slice);
+        Self::pack_hash_option(&config.fee_owner.map(|key| key.to_bytes()), buf);
+        buf.push(config.paused as u8);
+    }
+    /// An allowlist proof trails the bid data: the cap, the number of hashes
+    /// and the hashes. Bids in auctions that are not gated leave it out.
+    fn unpack_allowlist_proof(input: &[u8]) -> Result<Option<AllowlistProof>, ProgramError> {
+        if input.is_empty() {
+            return Ok(None);
+        }
+        let (cap, rest) = Self::unpack_u64(input)?;
+        let (&proof_len, mut rest) = rest.split_first().ok_or(AuctionError::InvalidInstruction)?;
+        let mut proof = Vec::with_capacity(proof_len as usize);
+        for _ in 0..proof_len {
+            let (hash, _rest) = Self::unpack_hash(rest)?;
+            proof.push(hash);
+            rest = _rest;
+        }
+        Ok(Some(AllowlistProof { cap, proof }))
+    }
+    fn pack_allowlist_proof(allowlist_proof: &Option<AllowlistProof>, buf: &mut Vec<u8>) {
+        if let Some(AllowlistProof { cap, proof }) = allowlist_proof {
+            buf.extend_from_slice(&cap.to_le_bytes());
+            buf.push(proof.len() as u8);
+            for hash in proof {
+                buf.extend_from_slice(hash);
+            }
+        }
+    }
+    fn pack_hash_option(hash: &Option<[u8; 32]>, buf: &mut Vec<u8>) {
+        match hash {
+            Some(hash) => {
+                buf.push(1);
+                buf.extend_from_slice(hash);
+            }
+            None => buf.push(0),
+        }
+    }
+
     /// Packs a [AuctionInstruction](enum.AuctionInstruction.html) into a byte buffer.
     pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
-        match &*self {
+        match self {
             Self::Initialize(InitializeData {
                 fees,
-                nonce,
+                auction_index,
                 start_timestamp,
                 end_timestamp,
+                max_winners,
+                lot_amount,
+                reserve_price,
+                reserve_commitment,
+                bid_rules,
+                soft_close,
+                auction_type,
+                dutch_price,
+                reveal_end_timestamp,
+                min_deposit,
+                pricing_rule,
+                buy_now_price,
+                candle_window,
+                retract_rules,
+                allowlist_root,
+                wallet_caps,
+                fee_splits,
             }) => {
                 buf.push(0);
                 let mut fees_slice = [0u8; AuctionFees::LEN];
                 Pack::pack_into_slice(fees, &mut fees_slice[..]);
                 buf.extend_from_slice(&fees_slice);
 
-                buf.push(*nonce);
+                buf.extend_from_slice(&auction_index.to_le_bytes());
                 buf.extend_from_slice(&start_timestamp.to_le_bytes());
                 buf.extend_from_slice(&end_timestamp.to_le_bytes());
-
+                buf.push(*max_winners);
+                buf.extend_from_slice(&lot_amount.to_le_bytes());
+                buf.extend_from_slice(&reserve_price.to_le_bytes());
+                Self::pack_hash_option(reserve_commitment, &mut buf);
+                let mut bid_rules_slice = [0u8; BidRules::LEN];
+                Pack::pack_into_slice(bid_rules, &mut bid_rules_slice[..]);
+                buf.extend_from_slice(&bid_rules_slice);
+                let mut soft_close_slice = [0u8; SoftClose::LEN];
+                Pack::pack_into_slice(soft_close, &mut soft_close_slice[..]);
+                buf.extend_from_slice(&soft_close_slice);
+                buf.push(*auction_type as u8);
+                let mut dutch_price_slice = [0u8; DutchPrice::LEN];
+                Pack::pack_into_slice(dutch_price, &mut dutch_price_slice[..]);
+                buf.extend_from_slice(&dutch_price_slice);
+                buf.extend_from_slice(&reveal_end_timestamp.to_le_bytes());
+                buf.extend_from_slice(&min_deposit.to_le_bytes());
+                buf.push(*pricing_rule as u8);
+                buf.extend_from_slice(&buy_now_price.to_le_bytes());
+                buf.extend_from_slice(&candle_window.to_le_bytes());
+                let mut retract_rules_slice = [0u8; RetractRules::LEN];
+                Pack::pack_into_slice(retract_rules, &mut retract_rules_slice[..]);
+                buf.extend_from_slice(&retract_rules_slice);
+                Self::pack_hash_option(allowlist_root, &mut buf);
+                let mut wallet_caps_slice = [0u8; WalletCaps::LEN];
+                Pack::pack_into_slice(wallet_caps, &mut wallet_caps_slice[..]);
+                buf.extend_from_slice(&wallet_caps_slice);
+                let mut fee_splits_slice = [0u8; FeeSplits::LEN];
+                Pack::pack_into_slice(fee_splits, &mut fee_splits_slice[..]);
+                buf.extend_from_slice(&fee_splits_slice);
             }
             Self::PlaceBid(PlaceBid {
                 bid_amount,
+                allowlist_proof,
             }) => {
                 buf.push(1);
                 buf.extend_from_slice(&bid_amount.to_le_bytes());
-            }
-            Self::Withdraw(Withdraw {
-                bid_amount,
-            }) => {
-                buf.push(2);
-                buf.extend_from_slice(&bid_amount.to_le_bytes());
+                Self::pack_allowlist_proof(allowlist_proof, &mut buf);
             }
             Self::Cancel(Cancel {
                 canceled,
@@ -226,9 +902,79 @@ impl AuctionInstruction {
                 buf.push(3);
                 buf.extend_from_slice(&canceled.to_le_bytes());
             }
+            Self::Settle(Settle { reserve_reveal }) => {
+                buf.push(4);
+                if let Some(ReserveReveal { reserve_price, salt }) = reserve_reveal {
+                    buf.extend_from_slice(&reserve_price.to_le_bytes());
+                    buf.extend_from_slice(salt);
+                }
+            }
+            Self::ClaimRefund => {
+                buf.push(5);
+            }
+            Self::CommitBid(CommitBid {
+                commitment,
+                deposit,
+                allowlist_proof,
+            }) => {
+                buf.push(6);
+                buf.extend_from_slice(commitment);
+                buf.extend_from_slice(&deposit.to_le_bytes());
+                Self::pack_allowlist_proof(allowlist_proof, &mut buf);
+            }
+            Self::RevealBid(RevealBid { amount, salt }) => {
+                buf.push(7);
+                buf.extend_from_slice(&amount.to_le_bytes());
+                buf.extend_from_slice(salt);
+            }
+            Self::PlaceUnitBid(PlaceUnitBid {
+                quantity,
+                unit_price,
+                allowlist_proof,
+            }) => {
+                buf.push(8);
+                buf.extend_from_slice(&quantity.to_le_bytes());
+                buf.extend_from_slice(&unit_price.to_le_bytes());
+                Self::pack_allowlist_proof(allowlist_proof, &mut buf);
+            }
+            Self::ClearBids => {
+                buf.push(9);
+            }
+            Self::ClaimFill => {
+                buf.push(10);
+            }
+            Self::ResolveCandle => {
+                buf.push(11);
+            }
+            Self::RetractBid => {
+                buf.push(12);
+            }
+            Self::SetAllowlist(SetAllowlist { allowlist_root }) => {
+                buf.push(13);
+                Self::pack_hash_option(allowlist_root, &mut buf);
+            }
+            Self::InitializeConfig(config) => {
+                buf.push(14);
+                Self::pack_config_data(config, &mut buf);
+            }
+            Self::UpdateConfig(config) => {
+                buf.push(15);
+                Self::pack_config_data(config, &mut buf);
+            }
+            Self::MigrateAuction => {
+                buf.push(16);
+            }
         }
         buf
     }
+
+    /// Borsh encodes a [AuctionInstruction](enum.AuctionInstruction.html)
+    /// behind `BORSH_INSTRUCTION_TAG`, the layout published in the schema.
+    pub fn pack_borsh(&self) -> Vec<u8> {
+        let mut buf = vec![BORSH_INSTRUCTION_TAG];
+        self.serialize(&mut buf).unwrap();
+        buf
+    }
 }
 
 /// Creates an 'initialize' instruction.
@@ -240,25 +986,71 @@ pub fn initialize(
     pool_pubkey: &Pubkey,
     fee_pubkey: &Pubkey,
     fees: AuctionFees,
-    nonce: u8,
+    auction_index: u64,
     start_timestamp: UnixTimestamp,
     end_timestamp: UnixTimestamp,
+    max_winners: u8,
+    lot_source_pubkey: &Pubkey,
+    lot_vault_pubkey: &Pubkey,
+    seller_pubkey: &Pubkey,
+    lot_amount: u64,
+    reserve_price: u64,
+    reserve_commitment: Option<[u8; 32]>,
+    bid_rules: BidRules,
+    soft_close: SoftClose,
+    auction_type: AuctionType,
+    dutch_price: DutchPrice,
+    reveal_end_timestamp: UnixTimestamp,
+    min_deposit: u64,
+    pricing_rule: PricingRule,
+    buy_now_price: u64,
+    candle_window: UnixTimestamp,
+    retract_rules: RetractRules,
+    allowlist_root: Option<[u8; 32]>,
+    wallet_caps: WalletCaps,
+    fee_splits: FeeSplits,
 ) -> Result<Instruction, ProgramError> {
     let init_data = AuctionInstruction::Initialize(InitializeData {
         fees,
-        nonce,
+        auction_index,
         start_timestamp,
         end_timestamp,
+        max_winners,
+        lot_amount,
+        reserve_price,
+        reserve_commitment,
+        bid_rules,
+        soft_close,
+        auction_type,
+        dutch_price,
+        reveal_end_timestamp,
+        min_deposit,
+        pricing_rule,
+        buy_now_price,
+        candle_window,
+        retract_rules,
+        allowlist_root,
+        wallet_caps,
+        fee_splits: fee_splits.clone(),
     });
     let data = init_data.pack();
 
-    let accounts = vec![
-        AccountMeta::new(*auction_pubkey, true),
+    let mut accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
         AccountMeta::new_readonly(*owner_token_pubkey, false),
         AccountMeta::new(*pool_pubkey, false),
         AccountMeta::new_readonly(*fee_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new(*lot_source_pubkey, false),
+        AccountMeta::new(*lot_vault_pubkey, false),
+        AccountMeta::new(*seller_pubkey, true),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
     ];
+    for share in fee_splits.active_shares() {
+        accounts.push(AccountMeta::new_readonly(share.account, false));
+    }
 
     Ok(Instruction {
         program_id: *program_id,
@@ -275,16 +1067,115 @@ pub fn place_bid(
     deposit_token_pubkey: &Pubkey,
     pool_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
+    bidder_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
     instruction: PlaceBid,
 ) -> Result<Instruction, ProgramError> {
     let data = AuctionInstruction::PlaceBid(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*auction_pubkey, false),
+        AccountMeta::new(*auction_pubkey, false),
         AccountMeta::new(*deposit_token_pubkey, false),
         AccountMeta::new(*pool_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
-        AccountMeta::new_readonly(*user_transfer_authority_pubkey, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new(*bidder_pubkey, true),
+        AccountMeta::new(*bid_record_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'place_bid' instruction that buys the lot at the buy-it-now
+/// price, with the accounts to settle the auction.
+pub fn buy_now(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    deposit_token_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    bidder_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    owner_token_pubkey: &Pubkey,
+    fee_account_pubkey: &Pubkey,
+    lot_vault_pubkey: &Pubkey,
+    seller_lot_pubkey: &Pubkey,
+    bidder_lot_pubkey: &Pubkey,
+    instruction: PlaceBid,
+) -> Result<Instruction, ProgramError> {
+    let mut ix = place_bid(
+        program_id,
+        token_program_id,
+        auction_pubkey,
+        deposit_token_pubkey,
+        pool_pubkey,
+        user_transfer_authority_pubkey,
+        bidder_pubkey,
+        bid_record_pubkey,
+        instruction,
+    )?;
+    ix.accounts.extend_from_slice(&[
+        AccountMeta::new(*authority_pubkey, false),
+        AccountMeta::new(*owner_token_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new(*lot_vault_pubkey, false),
+        AccountMeta::new(*seller_lot_pubkey, false),
+        AccountMeta::new(*bidder_lot_pubkey, false),
+    ]);
+    Ok(ix)
+}
+
+/// Returns the trailing accounts that unwrap the payouts of a native SOL
+/// auction, to append to a 'settle', 'claim_refund', 'claim_fill' or
+/// 'buy_now' instruction.
+pub fn native_accounts(program_id: &Pubkey, auction_pubkey: &Pubkey) -> Vec<AccountMeta> {
+    let (unwrap_pubkey, _) = find_unwrap_address(program_id, auction_pubkey);
+    vec![
+        AccountMeta::new(unwrap_pubkey, false),
+        AccountMeta::new_readonly(spl_token::native_mint::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ]
+}
+
+/// Returns the accounts receiving the royalty and marketplace shares, to
+/// append to a 'settle' or 'buy_now' instruction after the winners.
+pub fn fee_share_accounts(fee_splits: &FeeSplits) -> Vec<AccountMeta> {
+    fee_splits
+        .active_shares()
+        .map(|share| AccountMeta::new(share.account, false))
+        .collect()
+}
+
+/// Creates a 'cancel' instruction.
+pub fn cancel(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    auction_authority_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    lot_vault_pubkey: &Pubkey,
+    seller_lot_pubkey: &Pubkey,
+    instruction: Cancel,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::Cancel(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new_readonly(*auction_authority_pubkey, true),
+        AccountMeta::new_readonly(*authority_pubkey, false),
+        AccountMeta::new(*lot_vault_pubkey, false),
+        AccountMeta::new(*seller_lot_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
         AccountMeta::new_readonly(sysvar::clock::id(), false),
     ];
 
@@ -295,26 +1186,229 @@ pub fn place_bid(
     })
 }
 
-/// Creates a 'withdraw' instruction.
-pub fn withdraw(
+/// Creates a 'settle' instruction. `winner_pubkeys` holds the lot account of
+/// each winner, followed by its bid record and refund account under the
+/// second-price rule.
+pub fn settle(
     program_id: &Pubkey,
     token_program_id: &Pubkey,
     auction_pubkey: &Pubkey,
+    auction_authority_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
     pool_pubkey: &Pubkey,
+    owner_token_pubkey: &Pubkey,
     fee_account_pubkey: &Pubkey,
+    lot_vault_pubkey: &Pubkey,
+    seller_lot_pubkey: &Pubkey,
+    winner_pubkeys: &[Pubkey],
+    instruction: Settle,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::Settle(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new_readonly(*auction_authority_pubkey, true),
+        AccountMeta::new(*authority_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new(*owner_token_pubkey, false),
+        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new(*lot_vault_pubkey, false),
+        AccountMeta::new(*seller_lot_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+    for winner_pubkey in winner_pubkeys {
+        accounts.push(AccountMeta::new(*winner_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'claim_refund' instruction.
+pub fn claim_refund(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
     destination_token_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::ClaimRefund.pack();
+
+    let accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new(*authority_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new(*bid_record_pubkey, false),
+        AccountMeta::new(*destination_token_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'commit_bid' instruction.
+pub fn commit_bid(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    deposit_token_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    bidder_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
+    instruction: CommitBid,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::CommitBid(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new(*deposit_token_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new(*bidder_pubkey, true),
+        AccountMeta::new(*bid_record_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'reveal_bid' instruction.
+pub fn reveal_bid(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    deposit_token_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    bidder_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
+    instruction: RevealBid,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::RevealBid(instruction).pack();
+
+    let accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new(*deposit_token_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new_readonly(*bidder_pubkey, true),
+        AccountMeta::new(*bid_record_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'place_unit_bid' instruction.
+pub fn place_unit_bid(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    deposit_token_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
     user_transfer_authority_pubkey: &Pubkey,
-    instruction: Withdraw,
+    bidder_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
+    previous_pubkey: &Pubkey,
+    next_pubkey: Option<&Pubkey>,
+    instruction: PlaceUnitBid,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::PlaceUnitBid(instruction).pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new(*deposit_token_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(*user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new(*bidder_pubkey, true),
+        AccountMeta::new(*bid_record_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new(*previous_pubkey, false),
+    ];
+    if let Some(next_pubkey) = next_pubkey {
+        accounts.push(AccountMeta::new_readonly(*next_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'clear_bids' instruction.
+pub fn clear_bids(
+    program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    bid_record_pubkeys: &[Pubkey],
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::ClearBids.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+    for bid_record_pubkey in bid_record_pubkeys {
+        accounts.push(AccountMeta::new(*bid_record_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'claim_fill' instruction.
+pub fn claim_fill(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    lot_vault_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
+    destination_token_pubkey: &Pubkey,
+    destination_lot_pubkey: &Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = AuctionInstruction::Withdraw(instruction).pack();
+    let data = AuctionInstruction::ClaimFill.pack();
 
     let accounts = vec![
         AccountMeta::new_readonly(*auction_pubkey, false),
+        AccountMeta::new(*authority_pubkey, false),
         AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new(*lot_vault_pubkey, false),
+        AccountMeta::new(*bid_record_pubkey, false),
         AccountMeta::new(*destination_token_pubkey, false),
-        AccountMeta::new(*fee_account_pubkey, false),
+        AccountMeta::new(*destination_lot_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
-        AccountMeta::new_readonly(*user_transfer_authority_pubkey, false),
     ];
 
     Ok(Instruction {
@@ -324,16 +1418,152 @@ pub fn withdraw(
     })
 }
 
-/// Creates a 'cancel' instruction.
-pub fn cancel(
+/// Creates a 'resolve_candle' instruction.
+pub fn resolve_candle(
     program_id: &Pubkey,
     auction_pubkey: &Pubkey,
-    instruction: Cancel,
+    bid_record_pubkeys: &[Pubkey],
 ) -> Result<Instruction, ProgramError> {
-    let data = AuctionInstruction::Cancel(instruction).pack();
+    let data = AuctionInstruction::ResolveCandle.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new_readonly(sysvar::slot_hashes::id(), false),
+    ];
+    for bid_record_pubkey in bid_record_pubkeys {
+        accounts.push(AccountMeta::new(*bid_record_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'retract_bid' instruction. `penalty_recipient_pubkey` is the
+/// owner token account or the fee account under the penalty policy.
+pub fn retract_bid(
+    program_id: &Pubkey,
+    token_program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    pool_pubkey: &Pubkey,
+    bid_record_pubkey: &Pubkey,
+    destination_token_pubkey: &Pubkey,
+    bidder_pubkey: &Pubkey,
+    penalty_recipient_pubkey: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::RetractBid.pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new(*authority_pubkey, false),
+        AccountMeta::new(*pool_pubkey, false),
+        AccountMeta::new(*bid_record_pubkey, false),
+        AccountMeta::new(*destination_token_pubkey, false),
+        AccountMeta::new_readonly(*bidder_pubkey, true),
+        AccountMeta::new_readonly(*token_program_id, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+    if let Some(penalty_recipient_pubkey) = penalty_recipient_pubkey {
+        accounts.push(AccountMeta::new(*penalty_recipient_pubkey, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'set_allowlist' instruction.
+pub fn set_allowlist(
+    program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    auction_authority_pubkey: &Pubkey,
+    instruction: SetAllowlist,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::SetAllowlist(instruction).pack();
 
     let accounts = vec![
-        AccountMeta::new_readonly(*auction_pubkey, false),
+        AccountMeta::new(*auction_pubkey, false),
+        AccountMeta::new_readonly(*auction_authority_pubkey, true),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'initialize_config' instruction.
+pub fn initialize_config(
+    program_id: &Pubkey,
+    payer_pubkey: &Pubkey,
+    upgrade_authority_pubkey: &Pubkey,
+    config: ConfigData,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::InitializeConfig(config).pack();
+    let (program_data_pubkey, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+
+    let accounts = vec![
+        AccountMeta::new(find_config_address(program_id).0, false),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(program_data_pubkey, false),
+        AccountMeta::new_readonly(*upgrade_authority_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates an 'update_config' instruction.
+pub fn update_config(
+    program_id: &Pubkey,
+    owner_pubkey: &Pubkey,
+    config: ConfigData,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::UpdateConfig(config).pack();
+
+    let accounts = vec![
+        AccountMeta::new(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*owner_pubkey, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'migrate_auction' instruction.
+pub fn migrate_auction(
+    program_id: &Pubkey,
+    auction_pubkey: &Pubkey,
+    payer_pubkey: &Pubkey,
+    token_pubkey: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = AuctionInstruction::MigrateAuction.pack();
+    let (migration_pubkey, _) = find_migration_address(program_id, auction_pubkey);
+
+    let accounts = vec![
+        AccountMeta::new(*auction_pubkey, true),
+        AccountMeta::new(*payer_pubkey, true),
+        AccountMeta::new(migration_pubkey, false),
+        AccountMeta::new_readonly(*token_pubkey, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
     ];
 
     Ok(Instruction {
@@ -342,3 +1572,146 @@ pub fn cancel(
         data,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategies::*;
+    use proptest::{collection::vec, option, prelude::*};
+
+    fn allowlist_proofs() -> impl Strategy<Value = Option<AllowlistProof>> {
+        option::of(
+            (any::<u64>(), vec(any::<[u8; 32]>(), 0..4))
+                .prop_map(|(cap, proof)| AllowlistProof { cap, proof }),
+        )
+    }
+
+    fn config_data() -> impl Strategy<Value = ConfigData> {
+        (pubkeys(), auction_fees(), auction_fees(), option::of(pubkeys()), any::<bool>()).prop_map(
+            |(owner, min_fees, max_fees, fee_owner, paused)| ConfigData {
+                owner,
+                min_fees,
+                max_fees,
+                fee_owner,
+                paused,
+            },
+        )
+    }
+
+    fn initialize_data() -> impl Strategy<Value = InitializeData> {
+        let schedule = (
+            auction_fees(),
+            any::<u64>(),
+            any::<i64>(),
+            any::<i64>(),
+            any::<u8>(),
+            any::<u64>(),
+            any::<u64>(),
+            hash_options(),
+        );
+        let format = (
+            bid_rules(),
+            soft_closes(),
+            auction_types(),
+            dutch_prices(),
+            any::<i64>(),
+            any::<u64>(),
+            pricing_rules(),
+        );
+        let extensions = (
+            any::<u64>(),
+            any::<i64>(),
+            retract_rules(),
+            hash_options(),
+            wallet_caps(),
+            fee_splits(),
+        );
+        (schedule, format, extensions).prop_map(
+            |(
+                (fees, auction_index, start_timestamp, end_timestamp, max_winners, lot_amount, reserve_price, reserve_commitment),
+                (bid_rules, soft_close, auction_type, dutch_price, reveal_end_timestamp, min_deposit, pricing_rule),
+                (buy_now_price, candle_window, retract_rules, allowlist_root, wallet_caps, fee_splits),
+            )| InitializeData {
+                fees,
+                auction_index,
+                start_timestamp,
+                end_timestamp,
+                max_winners,
+                lot_amount,
+                reserve_price,
+                reserve_commitment,
+                bid_rules,
+                soft_close,
+                auction_type,
+                dutch_price,
+                reveal_end_timestamp,
+                min_deposit,
+                pricing_rule,
+                buy_now_price,
+                candle_window,
+                retract_rules,
+                allowlist_root,
+                wallet_caps,
+                fee_splits,
+            },
+        )
+    }
+
+    fn instructions() -> impl Strategy<Value = AuctionInstruction> {
+        prop_oneof![
+            initialize_data().prop_map(AuctionInstruction::Initialize),
+            (any::<u64>(), allowlist_proofs()).prop_map(|(bid_amount, allowlist_proof)| {
+                AuctionInstruction::PlaceBid(PlaceBid {
+                    bid_amount,
+                    allowlist_proof,
+                })
+            }),
+            any::<u8>().prop_map(|canceled| AuctionInstruction::Cancel(Cancel { canceled })),
+            option::of((any::<u64>(), any::<[u8; 32]>()).prop_map(|(reserve_price, salt)| {
+                ReserveReveal { reserve_price, salt }
+            }))
+            .prop_map(|reserve_reveal| AuctionInstruction::Settle(Settle { reserve_reveal })),
+            Just(AuctionInstruction::ClaimRefund),
+            (any::<[u8; 32]>(), any::<u64>(), allowlist_proofs()).prop_map(
+                |(commitment, deposit, allowlist_proof)| {
+                    AuctionInstruction::CommitBid(CommitBid {
+                        commitment,
+                        deposit,
+                        allowlist_proof,
+                    })
+                }
+            ),
+            (any::<u64>(), any::<[u8; 32]>())
+                .prop_map(|(amount, salt)| AuctionInstruction::RevealBid(RevealBid { amount, salt })),
+            (any::<u64>(), any::<u64>(), allowlist_proofs()).prop_map(
+                |(quantity, unit_price, allowlist_proof)| {
+                    AuctionInstruction::PlaceUnitBid(PlaceUnitBid {
+                        quantity,
+                        unit_price,
+                        allowlist_proof,
+                    })
+                }
+            ),
+            Just(AuctionInstruction::ClearBids),
+            Just(AuctionInstruction::ClaimFill),
+            Just(AuctionInstruction::ResolveCandle),
+            Just(AuctionInstruction::RetractBid),
+            hash_options().prop_map(|allowlist_root| {
+                AuctionInstruction::SetAllowlist(SetAllowlist { allowlist_root })
+            }),
+            config_data().prop_map(AuctionInstruction::InitializeConfig),
+            config_data().prop_map(AuctionInstruction::UpdateConfig),
+            Just(AuctionInstruction::MigrateAuction),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn pack_round_trip(instruction in instructions()) {
+            let packed = instruction.pack();
+            prop_assert_eq!(AuctionInstruction::unpack(&packed).unwrap(), instruction.clone());
+            let packed = instruction.pack_borsh();
+            prop_assert_eq!(AuctionInstruction::unpack(&packed).unwrap(), instruction);
+        }
+    }
+}
@@ -1,14 +1,23 @@
 //! A program for creating and managing farms
 //! by hongbo
+pub mod curve;
 pub mod error;
 pub mod instruction;
 pub mod processor;
 pub mod fees;
+pub mod rules;
+pub mod schema;
 pub mod state;
 
 #[cfg(not(feature = "no-entrypoint"))]
 mod entrypoint;
 
+#[cfg(test)]
+mod bank;
+
+#[cfg(test)]
+mod strategies;
+
 // Export current sdk types for downstream users building with a different sdk version
 pub use solana_program;
 
@@ -4,34 +4,65 @@
 use crate::{
     error::AuctionError,
     instruction::{
+        AllowlistProof,
         AuctionInstruction,
+        CommitBid,
+        ConfigData,
         InitializeData,
         PlaceBid,
-        Withdraw,
+        PlaceUnitBid,
+        ReserveReveal,
+        RevealBid,
+        SetAllowlist,
+        Settle,
         Cancel
     },
-    state::{AuctionV1, AuctionVersion},
-    fees::AuctionFees
+    rules::{PenaltyRecipient, RetractPolicy},
+    state::{
+        find_auction_address, find_authority_address, find_bid_record_address, find_config_address, find_migration_address, find_slot_hash, find_unwrap_address, hash_commitment, verify_allowlist_proof, AuctionState, AuctionStatus, AuctionType, AuctionV1, AuctionV2, AuctionVersion, BidRecord,
+        PricingRule, ProgramConfig, VersionedAccount,
+        AUCTION_SEED,
+        BID_RECORD_SEED,
+        BORSH_AUCTION_VERSION,
+        CONFIG_SEED,
+        MAX_WINNERS,
+        MIGRATION_SEED,
+        UNWRAP_SEED,
+    },
 };
 use num_traits::FromPrimitive;
 use std::convert::TryInto;
 
 use solana_program::{
     account_info::{next_account_info, AccountInfo}, 
+    bpf_loader_upgradeable,
     decode_error::DecodeError,
     entrypoint::ProgramResult,
     msg,
-    program::invoke_signed,
+    program::{invoke, invoke_signed},
     program_error::{PrintProgramError,ProgramError},
     program_pack::Pack,
     pubkey::Pubkey,
     clock::Clock,
     clock::UnixTimestamp,
-    sysvar::Sysvar,
+    rent::Rent,
+    system_instruction,
+    system_program,
+    sysvar::{self, Sysvar},
 };
+use arrayref::{array_ref, array_refs};
 pub struct Processor {
 }
 
+/// Accounts to unwrap SOL out of the pool of a native SOL auction
+struct NativeAccounts<'a, 'b> {
+    unwrap_info: &'b AccountInfo<'a>,
+    native_mint_info: &'b AccountInfo<'a>,
+    system_program_info: &'b AccountInfo<'a>,
+    rent_sysvar_info: &'b AccountInfo<'a>,
+    rent: Rent,
+}
+
 impl Processor {
     /// Unpacks a spl_token `Account`.
     pub fn unpack_token_account(
@@ -57,33 +88,233 @@ impl Processor {
                 .map_err(|_| AuctionError::ExpectedMint)
         }
     }
+    /// Calculates the authority id by generating a program address.
+    pub fn authority_id(
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        nonce: u8,
+    ) -> Result<Pubkey, AuctionError> {
+        Pubkey::create_program_address(&[&auction.to_bytes()[..32], &[nonce]], program_id)
+            .or(Err(AuctionError::InvalidProgramAddress))
+    }
+    /// Unpacks the program config. Production builds require it, others
+    /// create auctions without constraints until it is initialized.
+    fn unpack_config(
+        program_id: &Pubkey,
+        config_info: &AccountInfo,
+    ) -> Result<Option<ProgramConfig>, ProgramError> {
+        if *config_info.key != find_config_address(program_id).0 {
+            return Err(AuctionError::InvalidProgramConfig.into());
+        }
+        if config_info.data_is_empty() {
+            if cfg!(feature = "production") {
+                return Err(AuctionError::InvalidProgramConfig.into());
+            }
+            return Ok(None);
+        }
+        if config_info.owner != program_id {
+            return Err(AuctionError::InvalidProgramConfig.into());
+        }
+        Ok(Some(ProgramConfig::unpack_versioned(&config_info.data.borrow())?))
+    }
     /// Processes an [Initialize](enum.Instruction.html).
     pub fn process_initialize(
         program_id: &Pubkey,
-        fees: AuctionFees,
-        nonce: u8,
-        start_timestamp: UnixTimestamp,
-        end_timestamp: UnixTimestamp,
+        init_data: InitializeData,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
+        let InitializeData {
+            fees,
+            auction_index,
+            start_timestamp,
+            end_timestamp,
+            max_winners,
+            lot_amount,
+            reserve_price,
+            reserve_commitment,
+            bid_rules,
+            soft_close,
+            auction_type,
+            dutch_price,
+            reveal_end_timestamp,
+            min_deposit,
+            pricing_rule,
+            buy_now_price,
+            candle_window,
+            retract_rules,
+            allowlist_root,
+            wallet_caps,
+            fee_splits,
+        } = init_data;
         let account_info_iter = &mut accounts.iter();
         let auction_info = next_account_info(account_info_iter)?;
         let token_info = next_account_info(account_info_iter)?;
         let pool_info = next_account_info(account_info_iter)?;
         let fee_account_info = next_account_info(account_info_iter)?;
         let token_program_info = next_account_info(account_info_iter)?;
+        let lot_source_info = next_account_info(account_info_iter)?;
+        let lot_vault_info = next_account_info(account_info_iter)?;
+        let seller_info = next_account_info(account_info_iter)?;
+        let config_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
 
         let token_program_id = *token_program_info.key;
-        if AuctionVersion::is_initialized(&auction_info.data.borrow()) {
+        // the lot is only escrowed if the transfer into the vault is a real
+        // one, so no other token program is trusted
+        if token_program_id != spl_token::id() {
+            return Err(AuctionError::IncorrectTokenProgramId.into());
+        }
+        if !auction_info.data_is_empty() {
             return Err(AuctionError::AlreadyInUse.into());
         }
+        if !seller_info.is_signer {
+            return Err(AuctionError::InvalidAuthority.into());
+        }
 
+        let pool = Self::unpack_token_account(pool_info, &token_program_id)?;
+        let lot_vault = Self::unpack_token_account(lot_vault_info, &token_program_id)?;
+        let (auction_address, bump_seed) =
+            find_auction_address(program_id, seller_info.key, &lot_vault.mint, auction_index);
+        if auction_address != *auction_info.key {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+        let (authority_id, nonce) = find_authority_address(program_id, auction_info.key);
+        if pool.owner != authority_id || lot_vault.owner != authority_id {
+            return Err(AuctionError::InvalidOwner.into());
+        }
+        let config = Self::unpack_config(program_id, config_info)?;
+        if let Some(config) = &config {
+            if config.paused {
+                return Err(AuctionError::ProgramPaused.into());
+            }
+            if let Some(fee_owner) = config.fee_owner {
+                if Self::unpack_token_account(fee_account_info, &token_program_id)?.owner != fee_owner {
+                    return Err(AuctionError::IncorrectFeeAccount.into());
+                }
+            }
+        }
+        // shares are paid in the pool mint, a wrong account would fail
+        // every settlement
+        for share in fee_splits.active_shares() {
+            let share_info = next_account_info(account_info_iter)?;
+            if *share_info.key != share.account
+                || Self::unpack_token_account(share_info, &token_program_id)?.mint != pool.mint
+            {
+                return Err(AuctionError::IncorrectFeeAccount.into());
+            }
+        }
+        // bids in SOL are wrapped in a pool of the native mint, and the
+        // proceeds are unwrapped to the seller wallet
+        let native = pool.mint == spl_token::native_mint::id();
+        if native {
+            if *token_info.owner != system_program::id() {
+                return Err(AuctionError::InvalidOutput.into());
+            }
+            let authority_info = next_account_info(account_info_iter)?;
+            if *authority_info.key != authority_id {
+                return Err(AuctionError::InvalidProgramAddress.into());
+            }
+            // the program authority pays for the temporary unwrap account
+            let unwrap_rent = rent
+                .minimum_balance(spl_token::state::Account::LEN)
+                .saturating_sub(authority_info.lamports());
+            if unwrap_rent > 0 {
+                invoke(
+                    &system_instruction::transfer(seller_info.key, authority_info.key, unwrap_rent),
+                    &[
+                        seller_info.clone(),
+                        authority_info.clone(),
+                        system_program_info.clone(),
+                    ],
+                )?;
+            }
+        }
 
-        fees.validate()?;
+        fees.validate(config.as_ref())?;
+        fee_splits.validate(&fees)?;
 
-        let canceled = 0;
-        
-        let obj = AuctionVersion::AuctionV1(AuctionV1 {
+        if max_winners == 0 || max_winners as usize > MAX_WINNERS {
+            return Err(AuctionError::InvalidWinnerCount.into());
+        }
+        if lot_amount == 0 {
+            return Err(AuctionError::EmptyLot.into());
+        }
+        if reserve_commitment.is_some() && reserve_price != 0 {
+            return Err(AuctionError::InvalidReserve.into());
+        }
+        soft_close.validate()?;
+        if auction_type == AuctionType::Dutch {
+            if end_timestamp <= start_timestamp {
+                return Err(AuctionError::InvalidPriceCurve.into());
+            }
+            dutch_price.validate()?;
+        }
+        if auction_type == AuctionType::Sealed && reveal_end_timestamp <= end_timestamp {
+            return Err(AuctionError::InvalidPhase.into());
+        }
+        if pricing_rule == PricingRule::SecondPrice && auction_type != AuctionType::Sealed {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        // bids of a multi-unit auction are filled against the reserve
+        // before settlement, so it cannot stay hidden
+        if auction_type == AuctionType::MultiUnit && reserve_commitment.is_some() {
+            return Err(AuctionError::InvalidReserve.into());
+        }
+        // a buy-it-now bid settles at once, before a hidden reserve is revealed
+        if buy_now_price > 0
+            && (auction_type != AuctionType::English
+                || max_winners != 1
+                || reserve_commitment.is_some()
+                || buy_now_price < bid_rules.starting_price
+                || buy_now_price < reserve_price)
+        {
+            return Err(AuctionError::InvalidBuyNowPrice.into());
+        }
+        if auction_type == AuctionType::Candle
+            && (candle_window <= 0
+                || candle_window > end_timestamp.saturating_sub(start_timestamp)
+                || soft_close.extension_window != 0)
+        {
+            return Err(AuctionError::InvalidCandleWindow.into());
+        }
+        retract_rules.validate()?;
+        if retract_rules.policy != RetractPolicy::Forbidden && auction_type != AuctionType::English {
+            return Err(AuctionError::InvalidRetractPolicy.into());
+        }
+
+        let seller_bytes = seller_info.key.to_bytes();
+        let lot_mint_bytes = lot_vault.mint.to_bytes();
+        let auction_index_bytes = auction_index.to_le_bytes();
+        let auction_signature_seeds = [
+            AUCTION_SEED,
+            &seller_bytes[..],
+            &lot_mint_bytes[..],
+            &auction_index_bytes[..],
+            &[bump_seed],
+        ];
+        Self::create_account(
+            seller_info,
+            auction_info,
+            system_program_info,
+            rent,
+            AuctionVersion::LATEST_LEN,
+            program_id,
+            &[&auction_signature_seeds[..]],
+        )?;
+
+        Self::token_transfer(
+            auction_info.key,
+            token_program_info.clone(),
+            lot_source_info.clone(),
+            lot_vault_info.clone(),
+            seller_info.clone(),
+            nonce,
+            lot_amount,
+        )?;
+
+        let obj = AuctionVersion::AuctionV2(AuctionV2 {
             is_initialized: true,
             token_program_id,
             token: *token_info.key,
@@ -93,7 +324,44 @@ impl Processor {
             nonce,
             start_timestamp,
             end_timestamp,
-            canceled,
+            status: AuctionStatus::Active,
+            max_winners,
+            winners: Vec::new(),
+            lot_mint: lot_vault.mint,
+            lot_vault: *lot_vault_info.key,
+            lot_amount,
+            authority: *seller_info.key,
+            reserve_price,
+            reserve_commitment,
+            bid_rules,
+            soft_close,
+            total_extension: 0,
+            auction_type,
+            dutch_price,
+            reveal_end_timestamp,
+            min_deposit,
+            unrevealed_deposits: 0,
+            pricing_rule,
+            runner_up_bid: 0,
+            bid_count: 0,
+            processed_count: 0,
+            units_allocated: 0,
+            clearing_price: 0,
+            clearing_head: None,
+            buy_now_price,
+            candle_window,
+            candle_close_timestamp: 0,
+            candle_close_slot: 0,
+            native,
+            retract_rules,
+            standby: Vec::new(),
+            allowlist_root,
+            wallet_caps,
+            fee_splits,
+            bids_placed: 0,
+            last_bid_timestamp: 0,
+            auction_index,
+            bump_seed,
         });
         AuctionVersion::pack(obj, &mut auction_info.data.borrow_mut())?;
         Ok(())
@@ -126,9 +394,324 @@ impl Processor {
             signers,
         )
     }
+    /// Move a bidder's funds into the pool. In a native SOL auction the
+    /// source is the bidder wallet, and the lamports are wrapped in the pool.
+    #[allow(clippy::too_many_arguments)]
+    fn deposit<'a>(
+        auction: &Pubkey,
+        token_program_info: &AccountInfo<'a>,
+        source_info: &AccountInfo<'a>,
+        pool_info: &AccountInfo<'a>,
+        user_transfer_authority_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        nonce: u8,
+        amount: u64,
+        native: bool,
+    ) -> ProgramResult {
+        if !native {
+            return Self::token_transfer(
+                auction,
+                token_program_info.clone(),
+                source_info.clone(),
+                pool_info.clone(),
+                user_transfer_authority_info.clone(),
+                nonce,
+                amount,
+            );
+        }
+        invoke(
+            &system_instruction::transfer(source_info.key, pool_info.key, amount),
+            &[
+                source_info.clone(),
+                pool_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+        invoke(
+            &spl_token::instruction::sync_native(token_program_info.key, pool_info.key)?,
+            &[pool_info.clone(), token_program_info.clone()],
+        )
+    }
+    /// Check the allowlist proof of a bidder in a gated auction, and return
+    /// the cap it allows, zero for none
+    fn check_allowlist(
+        auction: &AuctionV2,
+        bidder: &Pubkey,
+        allowlist_proof: Option<&AllowlistProof>,
+    ) -> Result<u64, AuctionError> {
+        let root = match &auction.allowlist_root {
+            Some(root) => root,
+            None => return Ok(0),
+        };
+        match allowlist_proof {
+            Some(AllowlistProof { cap, proof }) if verify_allowlist_proof(root, bidder, *cap, proof) => {
+                Ok(*cap)
+            }
+            _ => Err(AuctionError::NotAllowlisted),
+        }
+    }
+    /// Hold the total escrowed by a bidder to the cap of the allowlist
+    fn check_allowlist_cap(bid_record: &mut BidRecord, allowlist_cap: u64) -> Result<(), AuctionError> {
+        bid_record.allowlist_cap = allowlist_cap;
+        if allowlist_cap > 0 && bid_record.amount > allowlist_cap {
+            return Err(AuctionError::AllowlistCapExceeded);
+        }
+        Ok(())
+    }
+    /// Read the accounts that unwrap SOL out of the pool of a native SOL
+    /// auction
+    fn next_native_accounts<'a, 'b, I: Iterator<Item = &'b AccountInfo<'a>>>(
+        account_info_iter: &mut I,
+    ) -> Result<NativeAccounts<'a, 'b>, ProgramError> {
+        let unwrap_info = next_account_info(account_info_iter)?;
+        let native_mint_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        if *native_mint_info.key != spl_token::native_mint::id()
+            || *system_program_info.key != system_program::id()
+        {
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(NativeAccounts {
+            unwrap_info,
+            native_mint_info,
+            system_program_info,
+            rent: Rent::from_account_info(rent_sysvar_info)?,
+            rent_sysvar_info,
+        })
+    }
+    /// Create an account owned by `owner` at a program address or a signing
+    /// keypair. The system program refuses to create an account that already
+    /// holds lamports, and anyone can send some to a known address, so a
+    /// funded account is topped up to rent exemption, allocated and assigned
+    /// instead.
+    fn create_account<'a>(
+        payer_info: &AccountInfo<'a>,
+        new_account_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        rent: &Rent,
+        space: usize,
+        owner: &Pubkey,
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let required_lamports = rent.minimum_balance(space);
+        if new_account_info.lamports() == 0 {
+            return invoke_signed(
+                &system_instruction::create_account(
+                    payer_info.key,
+                    new_account_info.key,
+                    required_lamports,
+                    space as u64,
+                    owner,
+                ),
+                &[
+                    payer_info.clone(),
+                    new_account_info.clone(),
+                    system_program_info.clone(),
+                ],
+                signers_seeds,
+            );
+        }
+        let top_up = required_lamports.saturating_sub(new_account_info.lamports());
+        if top_up > 0 {
+            invoke_signed(
+                &system_instruction::transfer(payer_info.key, new_account_info.key, top_up),
+                &[
+                    payer_info.clone(),
+                    new_account_info.clone(),
+                    system_program_info.clone(),
+                ],
+                signers_seeds,
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(new_account_info.key, space as u64),
+            &[new_account_info.clone(), system_program_info.clone()],
+            signers_seeds,
+        )?;
+        invoke_signed(
+            &system_instruction::assign(new_account_info.key, owner),
+            &[new_account_info.clone(), system_program_info.clone()],
+            signers_seeds,
+        )
+    }
+    /// Pay out of the pool: a token transfer, or for a native SOL auction
+    /// lamports sent to the destination wallet. To unwrap, the amount goes
+    /// through a temporary token account that the auction program authority
+    /// pays for and closes back to itself.
+    #[allow(clippy::too_many_arguments)]
+    fn pay_out<'a>(
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        token_program_info: &AccountInfo<'a>,
+        pool_info: &AccountInfo<'a>,
+        destination_info: &AccountInfo<'a>,
+        authority_info: &AccountInfo<'a>,
+        nonce: u8,
+        amount: u64,
+        native: Option<&NativeAccounts<'a, '_>>,
+    ) -> ProgramResult {
+        let native = match native {
+            Some(native) => native,
+            None => {
+                return Self::token_transfer(
+                    auction,
+                    token_program_info.clone(),
+                    pool_info.clone(),
+                    destination_info.clone(),
+                    authority_info.clone(),
+                    nonce,
+                    amount,
+                )
+            }
+        };
+        let (unwrap_address, bump) = find_unwrap_address(program_id, auction);
+        if unwrap_address != *native.unwrap_info.key {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let auction_bytes = auction.to_bytes();
+        let authority_signature_seeds = [&auction_bytes[..32], &[nonce]];
+        let unwrap_signature_seeds = [UNWRAP_SEED, &auction_bytes[..32], &[bump]];
+
+        // an unwrap account closed by an earlier payout of the same
+        // instruction keeps its data until the end of the transaction, and is
+        // used again
+        let closed_earlier = native.unwrap_info.owner == token_program_info.key
+            && native.unwrap_info.data_len() == spl_token::state::Account::LEN;
+        if !closed_earlier {
+            Self::create_account(
+                authority_info,
+                native.unwrap_info,
+                native.system_program_info,
+                &native.rent,
+                spl_token::state::Account::LEN,
+                token_program_info.key,
+                &[&authority_signature_seeds[..], &unwrap_signature_seeds[..]],
+            )?;
+            invoke(
+                &spl_token::instruction::initialize_account(
+                    token_program_info.key,
+                    native.unwrap_info.key,
+                    native.native_mint_info.key,
+                    authority_info.key,
+                )?,
+                &[
+                    native.unwrap_info.clone(),
+                    native.native_mint_info.clone(),
+                    authority_info.clone(),
+                    native.rent_sysvar_info.clone(),
+                    token_program_info.clone(),
+                ],
+            )?;
+        }
+        Self::token_transfer(
+            auction,
+            token_program_info.clone(),
+            pool_info.clone(),
+            native.unwrap_info.clone(),
+            authority_info.clone(),
+            nonce,
+            amount,
+        )?;
+        invoke_signed(
+            &spl_token::instruction::close_account(
+                token_program_info.key,
+                native.unwrap_info.key,
+                authority_info.key,
+                authority_info.key,
+                &[],
+            )?,
+            &[
+                native.unwrap_info.clone(),
+                authority_info.clone(),
+                token_program_info.clone(),
+            ],
+            &[&authority_signature_seeds[..]],
+        )?;
+        invoke_signed(
+            &system_instruction::transfer(authority_info.key, destination_info.key, amount),
+            &[
+                authority_info.clone(),
+                destination_info.clone(),
+                native.system_program_info.clone(),
+            ],
+            &[&authority_signature_seeds[..]],
+        )
+    }
+    /// Create the bid record of a bidder on their first bid, or add to it.
+    /// The updated record is returned for the caller to write back.
+    #[allow(clippy::too_many_arguments)]
+    fn record_bid<'a>(
+        program_id: &Pubkey,
+        auction_info: &AccountInfo<'a>,
+        bidder_info: &AccountInfo<'a>,
+        bid_record_info: &AccountInfo<'a>,
+        system_program_info: &AccountInfo<'a>,
+        rent: &Rent,
+        bid_amount: u64,
+        timestamp: UnixTimestamp,
+    ) -> Result<BidRecord, ProgramError> {
+        let (bid_record_address, bump) =
+            find_bid_record_address(program_id, auction_info.key, bidder_info.key);
+        if bid_record_address != *bid_record_info.key {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+
+        let mut bid_record = if bid_record_info.data_is_empty() {
+            let auction_bytes = auction_info.key.to_bytes();
+            let bidder_bytes = bidder_info.key.to_bytes();
+            let bid_record_signature_seeds = [
+                BID_RECORD_SEED,
+                &auction_bytes[..32],
+                &bidder_bytes[..32],
+                &[bump],
+            ];
+            Self::create_account(
+                bidder_info,
+                bid_record_info,
+                system_program_info,
+                rent,
+                BidRecord::SPACE,
+                program_id,
+                &[&bid_record_signature_seeds[..]],
+            )?;
+            BidRecord {
+                is_initialized: true,
+                auction: *auction_info.key,
+                bidder: *bidder_info.key,
+                ..BidRecord::default()
+            }
+        } else {
+            if bid_record_info.owner != program_id {
+                return Err(AuctionError::InvalidBidRecord.into());
+            }
+            let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+            if bid_record.claimed {
+                // a refunded bidder starts over with an empty escrow
+                bid_record.amount = 0;
+                bid_record.claimed = false;
+                bid_record.commitment = None;
+                bid_record.revealed = false;
+                bid_record.quantity = 0;
+                bid_record.unit_price = 0;
+                bid_record.filled = 0;
+                bid_record.cleared = false;
+                bid_record.bid_history.clear();
+            }
+            bid_record
+        };
+
+        bid_record.amount = bid_record
+            .amount
+            .checked_add(bid_amount)
+            .ok_or(AuctionError::CalculationFailure)?;
+        bid_record.last_bid_timestamp = timestamp;
+        Ok(bid_record)
+    }
     pub fn process_place_bid(
         program_id: &Pubkey,
         bid_amount: u64,
+        allowlist_proof: Option<AllowlistProof>,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
 
@@ -142,8 +725,21 @@ impl Processor {
         let clock_sysvar_info = next_account_info(account_info_iter)?;
         let clock = &Clock::from_account_info(clock_sysvar_info)?;
 
-        
-        let auction = &mut AuctionVersion::unpack(&auction_info.data.borrow())?;
+        let bidder_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+        if !bidder_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
 
         if clock.unix_timestamp > auction.end_timestamp() {
             msg!("This auction was ended!");
@@ -154,156 +750,1817 @@ impl Processor {
         else if auction.canceled() == 1 {
             msg!("This auction was canceled!");
         }
+        else if auction.settled() {
+            msg!("This auction was settled!");
+        }
         else {
-            Self::token_transfer(
-                auction_info.key,
-                token_program_info.clone(),
-                token_info.clone(),
-                pool_info.clone(),
-                user_transfer_authority_info.clone(),
-                auction.nonce(),
-                bid_amount,
+            if bid_amount == 0 {
+                return Err(AuctionError::BidIncrementTooSmall.into());
+            }
+            let allowlist_cap =
+                Self::check_allowlist(&auction, bidder_info.key, allowlist_proof.as_ref())?;
+            match auction.auction_type {
+                AuctionType::English | AuctionType::Candle => {
+                    let mut bid_record = Self::record_bid(
+                        program_id,
+                        auction_info,
+                        bidder_info,
+                        bid_record_info,
+                        system_program_info,
+                        rent,
+                        bid_amount,
+                        clock.unix_timestamp,
+                    )?;
+                    // a buy-it-now bid is charged the buy-it-now price, not
+                    // what it goes over it by
+                    let buy_now = auction.buy_now_price > 0
+                        && auction.reserve_commitment.is_none()
+                        && bid_record.amount >= auction.buy_now_price;
+                    let mut deposit = bid_amount;
+                    if buy_now {
+                        deposit = bid_amount
+                            .checked_sub(bid_record.amount - auction.buy_now_price)
+                            .ok_or(AuctionError::CalculationFailure)?;
+                        bid_record.amount = auction.buy_now_price;
+                    }
+                    Self::deposit(
+                        auction_info.key,
+                        token_program_info,
+                        token_info,
+                        pool_info,
+                        user_transfer_authority_info,
+                        system_program_info,
+                        auction.nonce(),
+                        deposit,
+                        auction.native,
+                    )?;
+                    auction.track_bid(clock.unix_timestamp);
+                    Self::check_allowlist_cap(&mut bid_record, allowlist_cap)?;
+                    auction
+                        .wallet_caps
+                        .check(bid_record.amount, auction.lot_per_winner())?;
+                    if auction.auction_type == AuctionType::Candle {
+                        // every bidder is counted again once the close is picked
+                        if bid_record.bid_history.is_empty() {
+                            auction.bid_count = auction
+                                .bid_count
+                                .checked_add(1)
+                                .ok_or(AuctionError::CalculationFailure)?;
+                        }
+                        bid_record
+                            .push_timed_bid(clock.unix_timestamp, auction.candle_window_start())?;
+                    }
+                    let total_amount = bid_record.amount;
+                    BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+                    if buy_now {
+                        if auction
+                            .highest_bid()
+                            .is_some_and(|w| w.bidder != *bidder_info.key && w.amount >= total_amount)
+                        {
+                            return Err(AuctionError::BuyNowUnavailable.into());
+                        }
+                        auction.place_winning_bid(bidder_info.key, total_amount)?;
+                        msg!("Bought now, auction ended and settled");
+                        auction.end_timestamp = clock.unix_timestamp;
+                        let authority_info = next_account_info(account_info_iter)?;
+                        let owner_token_info = next_account_info(account_info_iter)?;
+                        let fee_account_info = next_account_info(account_info_iter)?;
+                        let lot_vault_info = next_account_info(account_info_iter)?;
+                        let seller_lot_info = next_account_info(account_info_iter)?;
+                        Self::settle_auction(
+                            program_id,
+                            auction_info,
+                            &mut auction,
+                            authority_info,
+                            pool_info,
+                            owner_token_info,
+                            fee_account_info,
+                            lot_vault_info,
+                            seller_lot_info,
+                            token_program_info,
+                            account_info_iter.as_slice(),
+                        )?;
+                        AuctionVersion::pack(
+                            AuctionVersion::AuctionV2(auction),
+                            &mut auction_info.data.borrow_mut(),
+                        )?;
+                        return Ok(());
+                    }
+                    auction
+                        .bid_rules
+                        .check_bid(auction.bid_to_beat(bidder_info.key), total_amount)?;
+                    auction.place_retractable_bid(bidder_info.key, total_amount)?;
+                    if let Some((end_timestamp, total_extension)) = auction.soft_close.extend(
+                        auction.end_timestamp,
+                        auction.total_extension,
+                        clock.unix_timestamp,
+                    ) {
+                        msg!("Late bid, auction extended to {}", end_timestamp);
+                        auction.end_timestamp = end_timestamp;
+                        auction.total_extension = total_extension;
+                    }
+                }
+                AuctionType::Dutch => {
+                    if auction.is_over(clock.unix_timestamp) {
+                        return Err(AuctionError::Ended.into());
+                    }
+                    if auction.winners.iter().any(|w| w.bidder == *bidder_info.key) {
+                        return Err(AuctionError::AlreadyWinning.into());
+                    }
+                    let price = auction
+                        .dutch_price
+                        .price_at(auction.start_timestamp, auction.end_timestamp, clock.unix_timestamp)
+                        .ok_or(AuctionError::CalculationFailure)?;
+                    if bid_amount < price {
+                        return Err(AuctionError::BidTooLow.into());
+                    }
+                    Self::deposit(
+                        auction_info.key,
+                        token_program_info,
+                        token_info,
+                        pool_info,
+                        user_transfer_authority_info,
+                        system_program_info,
+                        auction.nonce(),
+                        price,
+                        auction.native,
+                    )?;
+                    let mut bid_record = Self::record_bid(
+                        program_id,
+                        auction_info,
+                        bidder_info,
+                        bid_record_info,
+                        system_program_info,
+                        rent,
+                        price,
+                        clock.unix_timestamp,
+                    )?;
+                    auction.track_bid(clock.unix_timestamp);
+                    Self::check_allowlist_cap(&mut bid_record, allowlist_cap)?;
+                    auction
+                        .wallet_caps
+                        .check(bid_record.amount, auction.lot_per_winner())?;
+                    BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+                    auction.place_winning_bid(bidder_info.key, price)?;
+                    if auction.is_over(clock.unix_timestamp) {
+                        msg!("All lots were bought");
+                    }
+                }
+                AuctionType::Sealed | AuctionType::MultiUnit => {
+                    return Err(AuctionError::WrongAuctionType.into())
+                }
+            }
+            AuctionVersion::pack(
+                AuctionVersion::AuctionV2(auction),
+                &mut auction_info.data.borrow_mut(),
             )?;
         }
         Ok(())
     }
-    pub fn process_withdraw(
+    /// Processes a [CommitBid](enum.Instruction.html).
+    pub fn process_commit_bid(
         program_id: &Pubkey,
-        bid_amount: u64,
+        commitment: [u8; 32],
+        deposit: u64,
+        allowlist_proof: Option<AllowlistProof>,
         accounts: &[AccountInfo],
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        let token_program_info = next_account_info(account_info_iter)?;
         let auction_info = next_account_info(account_info_iter)?;
+        let token_info = next_account_info(account_info_iter)?;
         let pool_info = next_account_info(account_info_iter)?;
-        let fee_account_info = next_account_info(account_info_iter)?;
-        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
         let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+        let bidder_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
 
-        let auction = AuctionVersion::unpack(&auction_info.data.borrow())?;
+        if !bidder_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
 
-        let withdraw_fee: u64 = to_u64(auction
-            .fees()
-            .auction_fee(to_u128(bid_amount)?)
-            .ok_or(AuctionError::FeeCalculationFailure)?)?;
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        if auction.auction_type != AuctionType::Sealed {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if clock.unix_timestamp < auction.start_timestamp()
+            || clock.unix_timestamp > auction.end_timestamp()
+        {
+            return Err(AuctionError::InvalidPhase.into());
+        }
+        if deposit == 0 || deposit < auction.min_deposit {
+            return Err(AuctionError::DepositTooLow.into());
+        }
+        let allowlist_cap = Self::check_allowlist(&auction, bidder_info.key, allowlist_proof.as_ref())?;
 
-        
-        Self::token_transfer(
+        Self::deposit(
             auction_info.key,
-            token_program_info.clone(),
-            destination_info.clone(),
-            pool_info.clone(),
-            user_transfer_authority_info.clone(),
+            token_program_info,
+            token_info,
+            pool_info,
+            user_transfer_authority_info,
+            system_program_info,
             auction.nonce(),
-            bid_amount,
+            deposit,
+            auction.native,
         )?;
-
-        //fee
-        Self::token_transfer(
-            auction_info.key,
-            token_program_info.clone(),
-            fee_account_info.clone(),
-            pool_info.clone(),
-            user_transfer_authority_info.clone(),
-            auction.nonce(),
-            withdraw_fee,
+        let mut bid_record = Self::record_bid(
+            program_id,
+            auction_info,
+            bidder_info,
+            bid_record_info,
+            system_program_info,
+            rent,
+            deposit,
+            clock.unix_timestamp,
         )?;
+        auction.track_bid(clock.unix_timestamp);
+        if bid_record.commitment.is_some() {
+            return Err(AuctionError::InvalidCommitment.into());
+        }
+        Self::check_allowlist_cap(&mut bid_record, allowlist_cap)?;
+        auction
+            .wallet_caps
+            .check(bid_record.amount, auction.lot_per_winner())?;
+        bid_record.commitment = Some(commitment);
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
 
+        auction.unrevealed_deposits = auction
+            .unrevealed_deposits
+            .checked_add(deposit)
+            .ok_or(AuctionError::CalculationFailure)?;
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
         Ok(())
     }
-    pub fn process_cancel(
+    /// Processes a [RevealBid](enum.Instruction.html).
+    pub fn process_reveal_bid(
         program_id: &Pubkey,
-        canceled: u8,
+        amount: u64,
+        salt: [u8; 32],
         accounts: &[AccountInfo],
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let auction_info = next_account_info(account_info_iter)?;
+        let token_info = next_account_info(account_info_iter)?;
+        let pool_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+        let bidder_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
 
-        let auction = AuctionVersion::unpack(&auction_info.data.borrow())?;
-        
-        let obj = AuctionVersion::AuctionV1(AuctionV1 {
-            is_initialized:auction.is_initialized(),
-            token_program_id: *auction.token_program_id(),
-            token: *auction.token_account(),
-            pool: *auction.pool(),
-            fee_account: *auction.fee_account(),
-            fees: auction.fees().clone(),
-            nonce: auction.nonce(),
-            start_timestamp:auction.start_timestamp(),
-            end_timestamp: auction.end_timestamp(),
-            canceled: canceled,
-        });
-        AuctionVersion::pack(obj, &mut auction_info.data.borrow_mut())?;
-        Ok(())
-    }
-    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult{
-        
-        let instruction = AuctionInstruction::unpack(input)?;
-        match instruction {
-            AuctionInstruction::Initialize(InitializeData {
-                fees,
-                nonce,
-                start_timestamp,
-                end_timestamp,
-            }) => {
-                msg!("Instruction: Init");
-                Self::process_initialize(
-                    program_id,
-                    fees,
-                    nonce,
-                    start_timestamp,
-                    end_timestamp,
-                    accounts,
-                )?;
-            }
-            AuctionInstruction::PlaceBid(PlaceBid {
-                bid_amount,
-            }) => {
-                msg!("Instruction: PlaceBid");
-                
-                Self::process_place_bid(
-                    program_id,
-                    bid_amount,
-                    accounts,
-                )?;
-                
-            }
-            AuctionInstruction::Withdraw(Withdraw {
-                bid_amount,
-            }) => {
-                msg!("Instruction: Withdraw");
-                
-                
-                Self::process_withdraw(
-                    program_id,
-                    bid_amount,
-                    accounts,
-                )?;
-            }
-            AuctionInstruction::Cancel(Cancel {
-                canceled,
-            }) => {
-                msg!("Instruction: Cancel");
-                
-                
-                Self::process_cancel(
-                    program_id,
-                    canceled,
-                    accounts,
-                )?;
+        if !bidder_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        if auction.auction_type != AuctionType::Sealed {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if clock.unix_timestamp <= auction.end_timestamp()
+            || clock.unix_timestamp > auction.reveal_end_timestamp()
+        {
+            return Err(AuctionError::InvalidPhase.into());
+        }
+
+        let (bid_record_address, _) =
+            find_bid_record_address(program_id, auction_info.key, bidder_info.key);
+        if bid_record_address != *bid_record_info.key || bid_record_info.owner != program_id {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+        let commitment = hash_commitment(auction_info.key, bidder_info.key, amount, &salt);
+        if bid_record.revealed || bid_record.commitment != Some(commitment) {
+            return Err(AuctionError::InvalidCommitment.into());
+        }
+
+        // the revealed bid is held to the cap proven at commitment
+        if bid_record.allowlist_cap > 0 && amount > bid_record.allowlist_cap {
+            return Err(AuctionError::AllowlistCapExceeded.into());
+        }
+        auction.wallet_caps.check(amount, auction.lot_per_winner())?;
+        let deposit = bid_record.amount;
+        if amount > deposit {
+            Self::deposit(
+                auction_info.key,
+                token_program_info,
+                token_info,
+                pool_info,
+                user_transfer_authority_info,
+                system_program_info,
+                auction.nonce(),
+                amount - deposit,
+                auction.native,
+            )?;
+            bid_record.amount = amount;
+        }
+        bid_record.revealed = true;
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+
+        auction.unrevealed_deposits = auction
+            .unrevealed_deposits
+            .checked_sub(deposit)
+            .ok_or(AuctionError::CalculationFailure)?;
+        // a revealed bid that does not win stays refundable, so revealing
+        // never fails because of the amount
+        if amount < auction.bid_rules.starting_price {
+            msg!("Revealed bid is below the starting price");
+        } else {
+            match auction.place_winning_bid(bidder_info.key, amount) {
+                Ok(Some(displaced)) => {
+                    auction.runner_up_bid = auction.runner_up_bid.max(displaced.amount);
+                }
+                Ok(None) => {}
+                Err(_) => {
+                    msg!("Revealed bid does not enter the winning set");
+                    auction.runner_up_bid = auction.runner_up_bid.max(amount);
+                }
             }
-            
         }
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
         Ok(())
     }
-}
+    /// Processes a [PlaceUnitBid](enum.Instruction.html).
+    pub fn process_place_unit_bid(
+        program_id: &Pubkey,
+        quantity: u64,
+        unit_price: u64,
+        allowlist_proof: Option<AllowlistProof>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let token_info = next_account_info(account_info_iter)?;
+        let pool_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let user_transfer_authority_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+        let bidder_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
 
+        if !bidder_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
 
-impl PrintProgramError for AuctionError {
-    fn print<E>(&self)
-    where
-        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
-    {
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        if auction.auction_type != AuctionType::MultiUnit {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if clock.unix_timestamp < auction.start_timestamp()
+            || clock.unix_timestamp > auction.end_timestamp()
+        {
+            return Err(AuctionError::InvalidPhase.into());
+        }
+        if quantity == 0 || quantity > auction.lot_amount() {
+            return Err(AuctionError::InvalidQuantity.into());
+        }
+        if unit_price == 0 || unit_price < auction.bid_rules.starting_price {
+            return Err(AuctionError::BidBelowStartingPrice.into());
+        }
+        let allowlist_cap = Self::check_allowlist(&auction, bidder_info.key, allowlist_proof.as_ref())?;
+        let escrow = to_u64(
+            to_u128(quantity)?
+                .checked_mul(to_u128(unit_price)?)
+                .ok_or(AuctionError::CalculationFailure)?,
+        )?;
+
+        Self::deposit(
+            auction_info.key,
+            token_program_info,
+            token_info,
+            pool_info,
+            user_transfer_authority_info,
+            system_program_info,
+            auction.nonce(),
+            escrow,
+            auction.native,
+        )?;
+        let mut bid_record = Self::record_bid(
+            program_id,
+            auction_info,
+            bidder_info,
+            bid_record_info,
+            system_program_info,
+            rent,
+            escrow,
+            clock.unix_timestamp,
+        )?;
+        auction.track_bid(clock.unix_timestamp);
+        if bid_record.quantity != 0 {
+            return Err(AuctionError::DuplicateBid.into());
+        }
+        Self::check_allowlist_cap(&mut bid_record, allowlist_cap)?;
+        auction.wallet_caps.check(bid_record.amount, quantity)?;
+        bid_record.quantity = quantity;
+        bid_record.unit_price = unit_price;
+        Self::link_unit_bid(
+            program_id,
+            auction_info,
+            &mut auction,
+            bid_record_info,
+            &mut bid_record,
+            account_info_iter,
+        )?;
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+
+        auction.bid_count = auction
+            .bid_count
+            .checked_add(1)
+            .ok_or(AuctionError::CalculationFailure)?;
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+    /// Unpack a unit bid record of the auction at `auction`
+    fn unpack_unit_bid(
+        program_id: &Pubkey,
+        auction: &Pubkey,
+        bid_record_info: &AccountInfo,
+    ) -> Result<BidRecord, ProgramError> {
+        if bid_record_info.owner != program_id {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        let bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+        if bid_record.auction != *auction || bid_record.quantity == 0 {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        Ok(bid_record)
+    }
+
+    /// Link a new unit bid into the clearing order, between the previous
+    /// record, or the auction when the bid clears first, and the record
+    /// linked after it
+    fn link_unit_bid<'a, 'b: 'a, I: Iterator<Item = &'a AccountInfo<'b>>>(
+        program_id: &Pubkey,
+        auction_info: &AccountInfo,
+        auction: &mut AuctionV2,
+        bid_record_info: &AccountInfo,
+        bid_record: &mut BidRecord,
+        account_info_iter: &mut I,
+    ) -> ProgramResult {
+        let previous_info = next_account_info(account_info_iter)?;
+        let key = bid_record.clearing_key();
+        let mut previous = None;
+        let next = if previous_info.key == auction_info.key {
+            auction.clearing_head
+        } else {
+            if previous_info.key == bid_record_info.key
+                || previous_info.data_len() == BidRecord::LEN
+            {
+                return Err(AuctionError::InvalidBidRecord.into());
+            }
+            let record = Self::unpack_unit_bid(program_id, auction_info.key, previous_info)?;
+            if record.clearing_key() >= key {
+                return Err(AuctionError::InvalidClearingOrder.into());
+            }
+            let next = record.next_bid;
+            previous = Some(record);
+            next
+        };
+        if let Some(next) = next {
+            let next_info = next_account_info(account_info_iter)?;
+            if *next_info.key != next {
+                return Err(AuctionError::InvalidClearingOrder.into());
+            }
+            let record = Self::unpack_unit_bid(program_id, auction_info.key, next_info)?;
+            if record.clearing_key() <= key {
+                return Err(AuctionError::InvalidClearingOrder.into());
+            }
+        }
+
+        bid_record.next_bid = next;
+        match previous {
+            Some(mut record) => {
+                record.next_bid = Some(*bid_record_info.key);
+                BidRecord::pack_versioned(record, &mut previous_info.data.borrow_mut())?;
+            }
+            None => auction.clearing_head = Some(*bid_record_info.key),
+        }
+        Ok(())
+    }
+
+    /// Processes a [ClearBids](enum.Instruction.html).
+    pub fn process_clear_bids(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+        let bid_record_infos = account_info_iter.as_slice();
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        if auction.auction_type != AuctionType::MultiUnit {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if auction.settled() {
+            return Err(AuctionError::AlreadySettled.into());
+        }
+        if !auction.is_over(clock.unix_timestamp) {
+            return Err(AuctionError::NotEnded.into());
+        }
+
+        for bid_record_info in bid_record_infos {
+            if bid_record_info.owner != program_id {
+                return Err(AuctionError::InvalidBidRecord.into());
+            }
+            let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+            if bid_record.auction != *auction_info.key {
+                return Err(AuctionError::InvalidBidRecord.into());
+            }
+            auction.clear_bid(bid_record_info.key, &mut bid_record)?;
+            BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+        }
+        msg!(
+            "Cleared {} of {} bids, clearing price {}",
+            auction.processed_count,
+            auction.bid_count,
+            auction.clearing_price
+        );
+
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+    /// Processes a [ResolveCandle](enum.Instruction.html).
+    pub fn process_resolve_candle(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+        let slot_hashes_info = next_account_info(account_info_iter)?;
+        let bid_record_infos = account_info_iter.as_slice();
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        if auction.auction_type != AuctionType::Candle {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if auction.settled() {
+            return Err(AuctionError::AlreadySettled.into());
+        }
+        if !auction.is_over(clock.unix_timestamp) {
+            return Err(AuctionError::NotEnded.into());
+        }
+
+        if auction.candle_close_timestamp == 0 {
+            if *slot_hashes_info.key != sysvar::slot_hashes::id() {
+                return Err(ProgramError::InvalidArgument);
+            }
+            if auction.candle_close_slot == 0 {
+                auction.candle_close_slot = AuctionV2::candle_close_slot_after(clock.slot);
+                msg!("Candle close will be drawn from slot {}", auction.candle_close_slot);
+                AuctionVersion::pack(
+                    AuctionVersion::AuctionV2(auction),
+                    &mut auction_info.data.borrow_mut(),
+                )?;
+                return Ok(());
+            }
+            if clock.slot <= auction.candle_close_slot {
+                return Err(AuctionError::InvalidPhase.into());
+            }
+            match find_slot_hash(&slot_hashes_info.data.borrow(), auction.candle_close_slot) {
+                Some(hash) => {
+                    auction.candle_close_timestamp = auction.draw_candle_close(&hash);
+                    // the live winners may hold bids placed after the close
+                    auction.winners.clear();
+                    msg!("Candle auction closed at {}", auction.candle_close_timestamp);
+                }
+                None => {
+                    // the slot hash left the history, a new slot is fixed
+                    // whose hash is not known yet either
+                    auction.candle_close_slot = AuctionV2::candle_close_slot_after(clock.slot);
+                    msg!("Candle close will be drawn from slot {}", auction.candle_close_slot);
+                    AuctionVersion::pack(
+                        AuctionVersion::AuctionV2(auction),
+                        &mut auction_info.data.borrow_mut(),
+                    )?;
+                    return Ok(());
+                }
+            }
+        }
+
+        for bid_record_info in bid_record_infos {
+            if bid_record_info.owner != program_id {
+                return Err(AuctionError::InvalidBidRecord.into());
+            }
+            let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+            if bid_record.auction != *auction_info.key || bid_record.cleared {
+                return Err(AuctionError::InvalidBidRecord.into());
+            }
+            if let Some(amount) = bid_record.amount_at(auction.candle_close_timestamp) {
+                if amount >= auction.bid_rules.starting_price
+                    && auction.place_winning_bid(&bid_record.bidder, amount).is_err()
+                {
+                    msg!("Bid at the close does not enter the winning set");
+                }
+            }
+            bid_record.cleared = true;
+            BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+            auction.processed_count = auction
+                .processed_count
+                .checked_add(1)
+                .ok_or(AuctionError::CalculationFailure)?;
+        }
+
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+    /// Refund a settled winner the escrow above the price they pay, leaving
+    /// exactly the price in their bid record
+    #[allow(clippy::too_many_arguments)]
+    fn refund_above_price<'a>(
+        program_id: &Pubkey,
+        auction_info: &AccountInfo<'a>,
+        token_program_info: &AccountInfo<'a>,
+        pool_info: &AccountInfo<'a>,
+        authority_info: &AccountInfo<'a>,
+        bid_record_info: &AccountInfo<'a>,
+        destination_info: &AccountInfo<'a>,
+        token_program_id: &Pubkey,
+        nonce: u8,
+        bidder: &Pubkey,
+        price: u64,
+        native: Option<&NativeAccounts<'a, '_>>,
+    ) -> ProgramResult {
+        if bid_record_info.owner != program_id {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+        if bid_record.auction != *auction_info.key || bid_record.bidder != *bidder {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        Self::check_destination(destination_info, token_program_id, bidder, native.is_some())?;
+        let refund = bid_record
+            .amount
+            .checked_sub(price)
+            .ok_or(AuctionError::CalculationFailure)?;
+        if refund > 0 {
+            Self::pay_out(
+                program_id,
+                auction_info.key,
+                token_program_info,
+                pool_info,
+                destination_info,
+                authority_info,
+                nonce,
+                refund,
+                native,
+            )?;
+        }
+        bid_record.amount = price;
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+        Ok(())
+    }
+    /// Check that a payout goes to `owner`: a token account it owns, or its
+    /// wallet itself when SOL is unwrapped
+    fn check_destination(
+        destination_info: &AccountInfo,
+        token_program_id: &Pubkey,
+        owner: &Pubkey,
+        native: bool,
+    ) -> Result<(), AuctionError> {
+        let destination_owner = if native {
+            *destination_info.key
+        } else {
+            Self::unpack_token_account(destination_info, token_program_id)?.owner
+        };
+        if destination_owner != *owner {
+            return Err(AuctionError::InvalidOutput);
+        }
+        Ok(())
+    }
+    /// Check that the auction account is owned by the program, an account of
+    /// another program could carry the same bytes
+    fn check_auction_account(program_id: &Pubkey, auction_info: &AccountInfo) -> ProgramResult {
+        if auction_info.owner != program_id {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+    /// Check that the auction authority signed the instruction
+    pub fn check_authority(
+        expected: &Pubkey,
+        authority_info: &AccountInfo,
+    ) -> Result<(), AuctionError> {
+        if !authority_info.is_signer || authority_info.key != expected {
+            return Err(AuctionError::InvalidAuthority);
+        }
+        Ok(())
+    }
+    /// Processes a [Cancel](enum.Instruction.html). Canceling is final: the
+    /// lot goes back to the seller and every bid becomes refundable, so it is
+    /// refused once the auction is over or a Dutch lot was bought.
+    pub fn process_cancel(
+        program_id: &Pubkey,
+        canceled: u8,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let lot_vault_info = next_account_info(account_info_iter)?;
+        let seller_lot_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        Self::check_authority(auction.authority(), admin_info)?;
+        if canceled == 0 {
+            return Err(AuctionError::InvalidInstruction.into());
+        }
+        match auction.status {
+            AuctionStatus::Active => {}
+            AuctionStatus::Canceled => return Err(AuctionError::Canceled.into()),
+            AuctionStatus::Settled => return Err(AuctionError::AlreadySettled.into()),
+        }
+        if auction.is_over(clock.unix_timestamp) {
+            return Err(AuctionError::Ended.into());
+        }
+        if auction.auction_type == AuctionType::Dutch && !auction.winners.is_empty() {
+            return Err(AuctionError::LotsSold.into());
+        }
+        if *authority_info.key != Self::authority_id(program_id, auction_info.key, auction.nonce)? {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != auction.token_program_id {
+            return Err(AuctionError::IncorrectTokenProgramId.into());
+        }
+        if *lot_vault_info.key != auction.lot_vault {
+            return Err(AuctionError::InvalidOutput.into());
+        }
+        let seller_lot = Self::unpack_token_account(seller_lot_info, &auction.token_program_id)?;
+        if seller_lot.owner != auction.authority || seller_lot.mint != auction.lot_mint {
+            return Err(AuctionError::InvalidOutput.into());
+        }
+
+        // the lot only leaves the vault at settlement, so all of it is
+        // still there
+        Self::token_transfer(
+            auction_info.key,
+            token_program_info.clone(),
+            lot_vault_info.clone(),
+            seller_lot_info.clone(),
+            authority_info.clone(),
+            auction.nonce,
+            auction.lot_amount,
+        )?;
+        auction.status = AuctionStatus::Canceled;
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+    /// Processes a [Settle](enum.Instruction.html).
+    pub fn process_settle(
+        program_id: &Pubkey,
+        reserve_reveal: Option<ReserveReveal>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let pool_info = next_account_info(account_info_iter)?;
+        let token_info = next_account_info(account_info_iter)?;
+        let fee_account_info = next_account_info(account_info_iter)?;
+        let lot_vault_info = next_account_info(account_info_iter)?;
+        let seller_lot_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+        let winner_infos = account_info_iter.as_slice();
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        Self::check_authority(auction.authority(), admin_info)?;
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if auction.settled() {
+            return Err(AuctionError::AlreadySettled.into());
+        }
+        if !auction.is_over(clock.unix_timestamp) {
+            return Err(AuctionError::NotEnded.into());
+        }
+
+        if let Some(reserve_commitment) = auction.reserve_commitment {
+            let reveal = reserve_reveal.ok_or(AuctionError::InvalidReserve)?;
+            // the seller commits to the reserve as a bidder does to a bid
+            let reveal_hash =
+                hash_commitment(auction_info.key, admin_info.key, reveal.reserve_price, &reveal.salt);
+            if reveal_hash != reserve_commitment {
+                return Err(AuctionError::InvalidReserve.into());
+            }
+            auction.reserve_price = reveal.reserve_price;
+        }
+        Self::settle_auction(
+            program_id,
+            auction_info,
+            &mut auction,
+            authority_info,
+            pool_info,
+            token_info,
+            fee_account_info,
+            lot_vault_info,
+            seller_lot_info,
+            token_program_info,
+            winner_infos,
+        )?;
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+    /// Pay out an auction that is over: deliver the lot to the winners and
+    /// the proceeds, less the auction fee and the fee shares, to the seller
+    #[allow(clippy::too_many_arguments)]
+    fn settle_auction<'a>(
+        program_id: &Pubkey,
+        auction_info: &AccountInfo<'a>,
+        auction: &mut AuctionV2,
+        authority_info: &AccountInfo<'a>,
+        pool_info: &AccountInfo<'a>,
+        token_info: &AccountInfo<'a>,
+        fee_account_info: &AccountInfo<'a>,
+        lot_vault_info: &AccountInfo<'a>,
+        seller_lot_info: &AccountInfo<'a>,
+        token_program_info: &AccountInfo<'a>,
+        winner_infos: &[AccountInfo<'a>],
+    ) -> ProgramResult {
+        if *authority_info.key != Self::authority_id(program_id, auction_info.key, auction.nonce())? {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != *auction.token_program_id() {
+            return Err(AuctionError::IncorrectTokenProgramId.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+        if *token_info.key != *auction.token_account() || *lot_vault_info.key != *auction.lot_vault() {
+            return Err(AuctionError::InvalidOutput.into());
+        }
+        if *fee_account_info.key != *auction.fee_account() {
+            return Err(AuctionError::IncorrectFeeAccount.into());
+        }
+
+        let token_program_id = *auction.token_program_id();
+        let seller = if auction.native {
+            *token_info.key
+        } else {
+            Self::unpack_token_account(token_info, &token_program_id)?.owner
+        };
+        let seller_lot = Self::unpack_token_account(seller_lot_info, &token_program_id)?;
+        if seller_lot.owner != seller {
+            return Err(AuctionError::InvalidOutput.into());
+        }
+        // winning bids below the reserve do not buy, their lots go back to
+        // the seller and the bids become refundable
+        let reserve_price = auction.reserve_price;
+        auction.winners.retain(|winner| winner.amount >= reserve_price);
+
+        if auction.auction_type == AuctionType::Candle
+            && (auction.candle_close_timestamp == 0 || auction.processed_count != auction.bid_count)
+        {
+            return Err(AuctionError::ClearingIncomplete.into());
+        }
+
+        // under the second-price rule each winner also passes a bid record
+        // and an account for the refund of the escrow above the price
+        let second_price = match auction.pricing_rule {
+            PricingRule::FirstPrice => None,
+            PricingRule::SecondPrice => Some(auction.second_price()),
+        };
+        let accounts_per_winner = if second_price.is_some() { 3 } else { 1 };
+        let winner_accounts_len = auction.winners().len() * accounts_per_winner;
+        if winner_infos.len() < winner_accounts_len {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let (winner_infos, trailing_infos) = winner_infos.split_at(winner_accounts_len);
+        let trailing_iter = &mut trailing_infos.iter();
+        let mut share_infos = Vec::new();
+        for share in auction.fee_splits.active_shares() {
+            let share_info = next_account_info(trailing_iter)?;
+            if *share_info.key != share.account {
+                return Err(AuctionError::IncorrectFeeAccount.into());
+            }
+            share_infos.push(share_info);
+        }
+        let native = if auction.native {
+            Some(Self::next_native_accounts(trailing_iter)?)
+        } else {
+            None
+        };
+
+        // every winner receives an equal share of the lot
+        let lot_per_winner = auction.lot_per_winner();
+        let nonce = auction.nonce();
+        let lot_mint = *auction.lot_mint();
+        let mut lot_sold: u64 = 0;
+        for (winner, winner_accounts) in auction
+            .winners
+            .iter_mut()
+            .zip(winner_infos.chunks(accounts_per_winner))
+        {
+            let winner_lot_info = &winner_accounts[0];
+            let winner_lot = Self::unpack_token_account(winner_lot_info, &token_program_id)?;
+            if winner_lot.owner != winner.bidder || winner_lot.mint != lot_mint {
+                return Err(AuctionError::InvalidOutput.into());
+            }
+            Self::token_transfer(
+                auction_info.key,
+                token_program_info.clone(),
+                lot_vault_info.clone(),
+                winner_lot_info.clone(),
+                authority_info.clone(),
+                nonce,
+                lot_per_winner,
+            )?;
+            if let Some(price) = second_price {
+                Self::refund_above_price(
+                    program_id,
+                    auction_info,
+                    token_program_info,
+                    pool_info,
+                    authority_info,
+                    &winner_accounts[1],
+                    &winner_accounts[2],
+                    &token_program_id,
+                    nonce,
+                    &winner.bidder,
+                    price,
+                    native.as_ref(),
+                )?;
+                winner.amount = price;
+            }
+            lot_sold = lot_sold
+                .checked_add(lot_per_winner)
+                .ok_or(AuctionError::CalculationFailure)?;
+        }
+        let mut proceeds = match second_price {
+            None => auction
+                .winners()
+                .iter()
+                .try_fold(0u64, |total, winner| total.checked_add(winner.amount))
+                .ok_or(AuctionError::CalculationFailure)?,
+            Some(price) => to_u64(
+                to_u128(price)?
+                    .checked_mul(auction.winners().len() as u128)
+                    .ok_or(AuctionError::CalculationFailure)?,
+            )?,
+        };
+        // the units of a multi-unit auction are delivered by ClaimFill, with
+        // every filled unit paid at the clearing price
+        if auction.auction_type == AuctionType::MultiUnit {
+            if auction.processed_count != auction.bid_count {
+                return Err(AuctionError::ClearingIncomplete.into());
+            }
+            proceeds = to_u64(
+                to_u128(auction.clearing_price)?
+                    .checked_mul(to_u128(auction.units_allocated)?)
+                    .ok_or(AuctionError::CalculationFailure)?,
+            )?;
+            lot_sold = auction.units_allocated;
+        }
+
+        // deposits of sealed bids that were never revealed are forfeited
+        proceeds = proceeds
+            .checked_add(auction.unrevealed_deposits)
+            .ok_or(AuctionError::CalculationFailure)?;
+        auction.unrevealed_deposits = 0;
+
+        let unsold = auction
+            .lot_amount()
+            .checked_sub(lot_sold)
+            .ok_or(AuctionError::CalculationFailure)?;
+        if unsold > 0 {
+            Self::token_transfer(
+                auction_info.key,
+                token_program_info.clone(),
+                lot_vault_info.clone(),
+                seller_lot_info.clone(),
+                authority_info.clone(),
+                auction.nonce(),
+                unsold,
+            )?;
+        }
+
+        let split = auction
+            .fee_splits
+            .split(&auction.fees, proceeds)
+            .ok_or(AuctionError::FeeCalculationFailure)?;
+        let seller_amount = split.seller;
+        let auction_fee = split.protocol_fee;
+        let splits = &auction.fee_splits;
+        let share_amounts = [
+            (splits.royalty.bps, split.royalty),
+            (splits.marketplace.bps, split.marketplace_fee),
+        ];
+        // share accounts were collected in active_shares order
+        let share_amounts = share_amounts
+            .iter()
+            .filter(|(bps, _)| *bps > 0)
+            .map(|(_, amount)| *amount);
+        for (share_info, amount) in share_infos.into_iter().zip(share_amounts) {
+            if amount > 0 {
+                Self::token_transfer(
+                    auction_info.key,
+                    token_program_info.clone(),
+                    pool_info.clone(),
+                    share_info.clone(),
+                    authority_info.clone(),
+                    auction.nonce(),
+                    amount,
+                )?;
+            }
+        }
+        if seller_amount > 0 {
+            Self::pay_out(
+                program_id,
+                auction_info.key,
+                token_program_info,
+                pool_info,
+                token_info,
+                authority_info,
+                auction.nonce(),
+                seller_amount,
+                native.as_ref(),
+            )?;
+        }
+        if auction_fee > 0 {
+            Self::token_transfer(
+                auction_info.key,
+                token_program_info.clone(),
+                pool_info.clone(),
+                fee_account_info.clone(),
+                authority_info.clone(),
+                auction.nonce(),
+                auction_fee,
+            )?;
+        }
+
+        auction.status = AuctionStatus::Settled;
+        Ok(())
+    }
+    /// Processes a [ClaimRefund](enum.Instruction.html).
+    pub fn process_claim_refund(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let pool_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        let native = if auction.native() {
+            Some(Self::next_native_accounts(account_info_iter)?)
+        } else {
+            None
+        };
+        if *authority_info.key != Self::authority_id(program_id, auction_info.key, auction.nonce())? {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != *auction.token_program_id() {
+            return Err(AuctionError::IncorrectTokenProgramId.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+
+        if bid_record_info.owner != program_id {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+        if bid_record.auction != *auction_info.key {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        if bid_record.claimed {
+            msg!("Bid was already refunded");
+            return Ok(());
+        }
+
+        let canceled = auction.canceled() == 1;
+        if bid_record.commitment.is_some() && !bid_record.revealed && !canceled {
+            return Err(AuctionError::BidNotRevealed.into());
+        }
+        if auction.auction_type() == AuctionType::MultiUnit && !canceled {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        // any bid may still win until the candle auction is resolved
+        if auction.auction_type() == AuctionType::Candle
+            && !canceled
+            && (auction.candle_close_timestamp() == 0
+                || auction.processed_count() != auction.bid_count())
+        {
+            return Err(AuctionError::ClearingIncomplete.into());
+        }
+        // a settled winner gets back what was escrowed above the winning bid
+        let refund = match auction
+            .winners()
+            .iter()
+            .find(|winner| winner.bidder == bid_record.bidder)
+        {
+            Some(winner) if !canceled => {
+                let excess = bid_record.amount.saturating_sub(winner.amount);
+                if !auction.settled() || excess == 0 {
+                    return Err(AuctionError::BidStillWinning.into());
+                }
+                excess
+            }
+            _ => bid_record.amount,
+        };
+
+        Self::check_destination(
+            destination_info,
+            auction.token_program_id(),
+            &bid_record.bidder,
+            native.is_some(),
+        )?;
+
+        Self::pay_out(
+            program_id,
+            auction_info.key,
+            token_program_info,
+            pool_info,
+            destination_info,
+            authority_info,
+            auction.nonce(),
+            refund,
+            native.as_ref(),
+        )?;
+
+        // a refunded bid can no longer move up when a winner retracts
+        if auction.standby().iter().any(|w| w.bidder == bid_record.bidder) {
+            auction.retract_bid(&bid_record.bidder);
+            AuctionVersion::pack(
+                AuctionVersion::AuctionV2(auction),
+                &mut auction_info.data.borrow_mut(),
+            )?;
+        }
+
+        bid_record.claimed = true;
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+        Ok(())
+    }
+    /// Processes a [ClaimFill](enum.Instruction.html).
+    pub fn process_claim_fill(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let pool_info = next_account_info(account_info_iter)?;
+        let lot_vault_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let destination_lot_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        let native = if auction.native() {
+            Some(Self::next_native_accounts(account_info_iter)?)
+        } else {
+            None
+        };
+        if auction.auction_type() != AuctionType::MultiUnit {
+            return Err(AuctionError::WrongAuctionType.into());
+        }
+        if *authority_info.key != Self::authority_id(program_id, auction_info.key, auction.nonce())? {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != *auction.token_program_id() {
+            return Err(AuctionError::IncorrectTokenProgramId.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+        if *lot_vault_info.key != *auction.lot_vault() {
+            return Err(AuctionError::InvalidOutput.into());
+        }
+        if !auction.settled() {
+            return Err(AuctionError::NotSettled.into());
+        }
+
+        if bid_record_info.owner != program_id {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+        if bid_record.auction != *auction_info.key || !bid_record.cleared {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        if bid_record.claimed {
+            msg!("Fill was already claimed");
+            return Ok(());
+        }
+
+        Self::check_destination(
+            destination_info,
+            auction.token_program_id(),
+            &bid_record.bidder,
+            native.is_some(),
+        )?;
+        let destination_lot =
+            Self::unpack_token_account(destination_lot_info, auction.token_program_id())?;
+        if destination_lot.owner != bid_record.bidder || destination_lot.mint != *auction.lot_mint() {
+            return Err(AuctionError::InvalidOutput.into());
+        }
+
+        let price = to_u64(
+            to_u128(auction.clearing_price())?
+                .checked_mul(to_u128(bid_record.filled)?)
+                .ok_or(AuctionError::CalculationFailure)?,
+        )?;
+        let refund = bid_record
+            .amount
+            .checked_sub(price)
+            .ok_or(AuctionError::CalculationFailure)?;
+        if bid_record.filled > 0 {
+            Self::token_transfer(
+                auction_info.key,
+                token_program_info.clone(),
+                lot_vault_info.clone(),
+                destination_lot_info.clone(),
+                authority_info.clone(),
+                auction.nonce(),
+                bid_record.filled,
+            )?;
+        }
+        if refund > 0 {
+            Self::pay_out(
+                program_id,
+                auction_info.key,
+                token_program_info,
+                pool_info,
+                destination_info,
+                authority_info,
+                auction.nonce(),
+                refund,
+                native.as_ref(),
+            )?;
+        }
+
+        bid_record.claimed = true;
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+        Ok(())
+    }
+    /// Processes a [RetractBid](enum.Instruction.html).
+    pub fn process_retract_bid(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let authority_info = next_account_info(account_info_iter)?;
+        let pool_info = next_account_info(account_info_iter)?;
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let destination_info = next_account_info(account_info_iter)?;
+        let bidder_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        let penalty_recipient_info = if auction.retract_rules.policy == RetractPolicy::Penalty {
+            Some(next_account_info(account_info_iter)?)
+        } else {
+            None
+        };
+        let native = if auction.native {
+            Some(Self::next_native_accounts(account_info_iter)?)
+        } else {
+            None
+        };
+        if !bidder_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if *authority_info.key != Self::authority_id(program_id, auction_info.key, auction.nonce())? {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+        if *token_program_info.key != *auction.token_program_id() {
+            return Err(AuctionError::IncorrectTokenProgramId.into());
+        }
+        if *pool_info.key != *auction.pool() {
+            return Err(AuctionError::IncorrectPoolAccount.into());
+        }
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if auction.settled() {
+            return Err(AuctionError::AlreadySettled.into());
+        }
+        if auction.is_over(clock.unix_timestamp) {
+            return Err(AuctionError::Ended.into());
+        }
+
+        if bid_record_info.owner != program_id {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        let mut bid_record = BidRecord::unpack_versioned(&bid_record_info.data.borrow())?;
+        if bid_record.auction != *auction_info.key || bid_record.bidder != *bidder_info.key {
+            return Err(AuctionError::InvalidBidRecord.into());
+        }
+        if bid_record.claimed {
+            msg!("Bid was already refunded");
+            return Ok(());
+        }
+        let leading = auction.winners.iter().any(|w| w.bidder == bid_record.bidder);
+        auction.retract_rules.check_retract(leading)?;
+        Self::check_destination(
+            destination_info,
+            auction.token_program_id(),
+            &bid_record.bidder,
+            native.is_some(),
+        )?;
+
+        let penalty = auction
+            .retract_rules
+            .penalty(bid_record.amount)
+            .ok_or(AuctionError::CalculationFailure)?;
+        if let Some(penalty_recipient_info) = penalty_recipient_info {
+            match auction.retract_rules.penalty_recipient {
+                PenaltyRecipient::Seller => {
+                    if *penalty_recipient_info.key != auction.token {
+                        return Err(AuctionError::InvalidOutput.into());
+                    }
+                    if penalty > 0 {
+                        Self::pay_out(
+                            program_id,
+                            auction_info.key,
+                            token_program_info,
+                            pool_info,
+                            penalty_recipient_info,
+                            authority_info,
+                            auction.nonce(),
+                            penalty,
+                            native.as_ref(),
+                        )?;
+                    }
+                }
+                PenaltyRecipient::FeeAccount => {
+                    if *penalty_recipient_info.key != *auction.fee_account() {
+                        return Err(AuctionError::IncorrectFeeAccount.into());
+                    }
+                    if penalty > 0 {
+                        Self::token_transfer(
+                            auction_info.key,
+                            token_program_info.clone(),
+                            pool_info.clone(),
+                            penalty_recipient_info.clone(),
+                            authority_info.clone(),
+                            auction.nonce(),
+                            penalty,
+                        )?;
+                    }
+                }
+            }
+        }
+        let refund = bid_record.amount - penalty;
+        if refund > 0 {
+            Self::pay_out(
+                program_id,
+                auction_info.key,
+                token_program_info,
+                pool_info,
+                destination_info,
+                authority_info,
+                auction.nonce(),
+                refund,
+                native.as_ref(),
+            )?;
+        }
+
+        if auction.retract_bid(&bid_record.bidder) {
+            match auction.highest_bid() {
+                Some(leader) => msg!("Winning bid retracted, leading bid is now {}", leader.amount),
+                None => msg!("Winning bid retracted, no bid is leading"),
+            }
+        }
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        bid_record.claimed = true;
+        BidRecord::pack_versioned(bid_record, &mut bid_record_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Processes a [SetAllowlist](enum.Instruction.html).
+    pub fn process_set_allowlist(
+        program_id: &Pubkey,
+        allowlist_root: Option<[u8; 32]>,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let admin_info = next_account_info(account_info_iter)?;
+        let clock_sysvar_info = next_account_info(account_info_iter)?;
+        let clock = &Clock::from_account_info(clock_sysvar_info)?;
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let mut auction = AuctionVersion::unpack_latest(&auction_info.data.borrow())?;
+        Self::check_authority(auction.authority(), admin_info)?;
+        if auction.canceled() == 1 {
+            return Err(AuctionError::Canceled.into());
+        }
+        if clock.unix_timestamp >= auction.start_timestamp() {
+            return Err(AuctionError::InvalidPhase.into());
+        }
+        auction.allowlist_root = allowlist_root;
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut auction_info.data.borrow_mut(),
+        )?;
+        Ok(())
+    }
+
+    /// Processes an [InitializeConfig](enum.Instruction.html).
+    pub fn process_initialize_config(
+        program_id: &Pubkey,
+        config_data: ConfigData,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let config_info = next_account_info(account_info_iter)?;
+        let payer_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+        let program_data_info = next_account_info(account_info_iter)?;
+        let upgrade_authority_info = next_account_info(account_info_iter)?;
+
+        Self::check_upgrade_authority(program_id, program_data_info, upgrade_authority_info)?;
+        let (config_address, bump_seed) = find_config_address(program_id);
+        if *config_info.key != config_address {
+            return Err(AuctionError::InvalidProgramConfig.into());
+        }
+        if !config_info.data_is_empty() {
+            return Err(AuctionError::AlreadyInUse.into());
+        }
+        let config = ProgramConfig {
+            is_initialized: true,
+            bump_seed,
+            owner: config_data.owner,
+            min_fees: config_data.min_fees,
+            max_fees: config_data.max_fees,
+            fee_owner: config_data.fee_owner,
+            paused: config_data.paused,
+        };
+        config.validate()?;
+
+        Self::create_account(
+            payer_info,
+            config_info,
+            system_program_info,
+            rent,
+            ProgramConfig::SPACE,
+            program_id,
+            &[&[CONFIG_SEED, &[bump_seed]]],
+        )?;
+        ProgramConfig::pack_versioned(config, &mut config_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Check that `upgrade_authority_info` is the upgrade authority of the
+    /// program, recorded in its program data account, and signed
+    fn check_upgrade_authority(
+        program_id: &Pubkey,
+        program_data_info: &AccountInfo,
+        upgrade_authority_info: &AccountInfo,
+    ) -> ProgramResult {
+        let (program_data_address, _) =
+            Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+        if *program_data_info.key != program_data_address
+            || *program_data_info.owner != bpf_loader_upgradeable::id()
+            || !upgrade_authority_info.is_signer
+        {
+            return Err(AuctionError::InvalidUpgradeAuthority.into());
+        }
+        // the bincode encoded UpgradeableLoaderState::ProgramData: a u32
+        // variant index, the deployment slot and the optional authority
+        let data = program_data_info.data.borrow();
+        let header = data
+            .get(..45)
+            .ok_or(AuctionError::InvalidUpgradeAuthority)?;
+        let header = array_ref![header, 0, 45];
+        let (variant, _slot, authority_tag, authority) = array_refs![header, 4, 8, 1, 32];
+        if u32::from_le_bytes(*variant) != 3
+            || authority_tag[0] != 1
+            || *authority != upgrade_authority_info.key.to_bytes()
+        {
+            return Err(AuctionError::InvalidUpgradeAuthority.into());
+        }
+        Ok(())
+    }
+
+    /// Processes an [UpdateConfig](enum.Instruction.html).
+    pub fn process_update_config(
+        program_id: &Pubkey,
+        config_data: ConfigData,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let config_info = next_account_info(account_info_iter)?;
+        let owner_info = next_account_info(account_info_iter)?;
+
+        let mut config = Self::unpack_config(program_id, config_info)?
+            .ok_or(AuctionError::InvalidProgramConfig)?;
+        if !owner_info.is_signer || *owner_info.key != config.owner {
+            return Err(AuctionError::InvalidAuthority.into());
+        }
+        config.owner = config_data.owner;
+        config.min_fees = config_data.min_fees;
+        config.max_fees = config_data.max_fees;
+        config.fee_owner = config_data.fee_owner;
+        config.paused = config_data.paused;
+        config.validate()?;
+        ProgramConfig::pack_versioned(config, &mut config_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /// Move the lamports of an account owned by the program to
+    /// `destination_info` and zero its data, so the runtime deletes it at the
+    /// end of the transaction
+    fn close_account(account_info: &AccountInfo, destination_info: &AccountInfo) -> ProgramResult {
+        let lamports = account_info.lamports();
+        **destination_info.lamports.borrow_mut() = destination_info
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(AuctionError::CalculationFailure)?;
+        **account_info.lamports.borrow_mut() = 0;
+        account_info.data.borrow_mut().fill(0);
+        Ok(())
+    }
+
+    /// Processes a [MigrateAuction](enum.Instruction.html).
+    pub fn process_migrate_auction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let auction_info = next_account_info(account_info_iter)?;
+        let payer_info = next_account_info(account_info_iter)?;
+        let migration_info = next_account_info(account_info_iter)?;
+        let token_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let rent_sysvar_info = next_account_info(account_info_iter)?;
+        let rent = &Rent::from_account_info(rent_sysvar_info)?;
+
+        if !auction_info.is_signer {
+            return Err(AuctionError::InvalidAuthority.into());
+        }
+        let (migration_address, bump_seed) = find_migration_address(program_id, auction_info.key);
+        if *migration_info.key != migration_address {
+            return Err(AuctionError::InvalidProgramAddress.into());
+        }
+
+        // Second step, the auction account was closed by the first one and
+        // is recreated at the latest size from the copy
+        if auction_info.data_is_empty() {
+            if migration_info.owner != program_id {
+                return Err(ProgramError::UninitializedAccount);
+            }
+            let auction = AuctionVersion::unpack_latest(&migration_info.data.borrow())?;
+            Self::create_account(
+                payer_info,
+                auction_info,
+                system_program_info,
+                rent,
+                AuctionVersion::LATEST_LEN,
+                program_id,
+                &[],
+            )?;
+            AuctionVersion::pack(
+                AuctionVersion::AuctionV2(auction),
+                &mut auction_info.data.borrow_mut(),
+            )?;
+            return Self::close_account(migration_info, payer_info);
+        }
+
+        Self::check_auction_account(program_id, auction_info)?;
+        let auction = match auction_info.data.borrow().split_first() {
+            Some((1, rest)) if auction_info.data_len() == 1 + AuctionV1::LEN => {
+                AuctionV1::unpack(rest)?
+            }
+            Some((&BORSH_AUCTION_VERSION, _)) => {
+                msg!("The auction is already at the latest version");
+                return Ok(());
+            }
+            _ => return Err(ProgramError::UninitializedAccount),
+        };
+
+        // First step, the state is copied to the migration account and the
+        // auction account closed, it cannot grow in place
+        if *token_info.key != auction.token {
+            return Err(AuctionError::InvalidOwner.into());
+        }
+        let token = Self::unpack_token_account(token_info, &auction.token_program_id)?;
+        let auction = AuctionV2 {
+            authority: token.owner,
+            ..AuctionV2::from(auction)
+        };
+        Self::create_account(
+            payer_info,
+            migration_info,
+            system_program_info,
+            rent,
+            AuctionVersion::LATEST_LEN,
+            program_id,
+            &[&[MIGRATION_SEED, &auction_info.key.to_bytes(), &[bump_seed]]],
+        )?;
+        AuctionVersion::pack(
+            AuctionVersion::AuctionV2(auction),
+            &mut migration_info.data.borrow_mut(),
+        )?;
+        msg!("Run MigrateAuction again in another transaction to recreate the auction");
+        Self::close_account(auction_info, payer_info)
+    }
+
+    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult{
+        
+        let instruction = AuctionInstruction::unpack(input)?;
+        match instruction {
+            AuctionInstruction::Initialize(init_data) => {
+                msg!("Instruction: Init");
+                Self::process_initialize(
+                    program_id,
+                    init_data,
+                    accounts,
+                )?;
+            }
+            AuctionInstruction::PlaceBid(PlaceBid {
+                bid_amount,
+                allowlist_proof,
+            }) => {
+                msg!("Instruction: PlaceBid");
+                
+                Self::process_place_bid(
+                    program_id,
+                    bid_amount,
+                    allowlist_proof,
+                    accounts,
+                )?;
+                
+            }
+            AuctionInstruction::Cancel(Cancel {
+                canceled,
+            }) => {
+                msg!("Instruction: Cancel");
+                
+                
+                Self::process_cancel(
+                    program_id,
+                    canceled,
+                    accounts,
+                )?;
+            }
+            AuctionInstruction::Settle(Settle {
+                reserve_reveal,
+            }) => {
+                msg!("Instruction: Settle");
+                Self::process_settle(program_id, reserve_reveal, accounts)?;
+            }
+            AuctionInstruction::ClaimRefund => {
+                msg!("Instruction: ClaimRefund");
+                Self::process_claim_refund(program_id, accounts)?;
+            }
+            AuctionInstruction::CommitBid(CommitBid {
+                commitment,
+                deposit,
+                allowlist_proof,
+            }) => {
+                msg!("Instruction: CommitBid");
+                Self::process_commit_bid(program_id, commitment, deposit, allowlist_proof, accounts)?;
+            }
+            AuctionInstruction::RevealBid(RevealBid { amount, salt }) => {
+                msg!("Instruction: RevealBid");
+                Self::process_reveal_bid(program_id, amount, salt, accounts)?;
+            }
+            AuctionInstruction::PlaceUnitBid(PlaceUnitBid {
+                quantity,
+                unit_price,
+                allowlist_proof,
+            }) => {
+                msg!("Instruction: PlaceUnitBid");
+                Self::process_place_unit_bid(
+                    program_id,
+                    quantity,
+                    unit_price,
+                    allowlist_proof,
+                    accounts,
+                )?;
+            }
+            AuctionInstruction::ClearBids => {
+                msg!("Instruction: ClearBids");
+                Self::process_clear_bids(program_id, accounts)?;
+            }
+            AuctionInstruction::ClaimFill => {
+                msg!("Instruction: ClaimFill");
+                Self::process_claim_fill(program_id, accounts)?;
+            }
+            AuctionInstruction::ResolveCandle => {
+                msg!("Instruction: ResolveCandle");
+                Self::process_resolve_candle(program_id, accounts)?;
+            }
+            AuctionInstruction::RetractBid => {
+                msg!("Instruction: RetractBid");
+                Self::process_retract_bid(program_id, accounts)?;
+            }
+            AuctionInstruction::SetAllowlist(SetAllowlist { allowlist_root }) => {
+                msg!("Instruction: SetAllowlist");
+                Self::process_set_allowlist(program_id, allowlist_root, accounts)?;
+            }
+            AuctionInstruction::InitializeConfig(config_data) => {
+                msg!("Instruction: InitializeConfig");
+                Self::process_initialize_config(program_id, config_data, accounts)?;
+            }
+            AuctionInstruction::UpdateConfig(config_data) => {
+                msg!("Instruction: UpdateConfig");
+                Self::process_update_config(program_id, config_data, accounts)?;
+            }
+            AuctionInstruction::MigrateAuction => {
+                msg!("Instruction: MigrateAuction");
+                Self::process_migrate_auction(program_id, accounts)?;
+            }
+            
+        }
+        Ok(())
+    }
+}
+
+
+impl PrintProgramError for AuctionError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
         match self {
             AuctionError::AlreadyInUse => msg!("Error: Farm account already in use"),
             AuctionError::InvalidProgramAddress => {
@@ -339,15 +2596,1053 @@ impl PrintProgramError for AuctionError {
             AuctionError::Canceled => {
                 msg!("The auction was canceled")
             }
+            AuctionError::IncorrectPoolAccount => msg!("Error: Pool token account incorrect"),
+            AuctionError::InvalidBidRecord => {
+                msg!("Error: Bid record address or contents do not match the auction and bidder")
+            }
+            AuctionError::BidTooLow => {
+                msg!("Error: The bid is not high enough to enter the winning set")
+            }
+            AuctionError::InvalidWinnerCount => {
+                msg!("Error: The number of winners must be between one and the supported maximum")
+            }
+            AuctionError::EmptyLot => msg!("Error: The auctioned lot amount must be non zero"),
+            AuctionError::NotEnded => msg!("Error: The auction has not ended yet"),
+            AuctionError::AlreadySettled => msg!("Error: The auction was already settled"),
+            AuctionError::InvalidAuthority => {
+                msg!("Error: The auction authority is missing or did not sign")
+            }
+            AuctionError::InvalidReserve => msg!(
+                "Error: The reserve price is set together with a commitment or does not match it"
+            ),
+            AuctionError::BidBelowStartingPrice => msg!("Error: The bid is below the starting price"),
+            AuctionError::BidIncrementTooSmall => {
+                msg!("Error: The bid does not beat the current bid by the minimum increment")
+            }
+            AuctionError::InvalidSoftClose => {
+                msg!("Error: The soft close window and durations must not be negative")
+            }
+            AuctionError::InvalidPriceCurve => {
+                msg!("Error: The Dutch auction price range or curve parameters are invalid")
+            }
+            AuctionError::AlreadyWinning => msg!("Error: The bidder already holds a winning bid"),
+            AuctionError::WrongAuctionType => {
+                msg!("Error: The instruction is not supported by this auction type")
+            }
+            AuctionError::InvalidPhase => {
+                msg!("Error: The instruction is not allowed in the current phase of the auction")
+            }
+            AuctionError::InvalidCommitment => msg!(
+                "Error: The bid commitment is missing, already made or does not match the revealed bid"
+            ),
+            AuctionError::DepositTooLow => {
+                msg!("Error: The deposit is below the minimum deposit of the auction")
+            }
+            AuctionError::BidNotRevealed => msg!("Error: An unrevealed sealed bid cannot be refunded"),
+            AuctionError::InvalidQuantity => {
+                msg!("Error: The bid quantity must be between one and the lot amount")
+            }
+            AuctionError::DuplicateBid => msg!("Error: The bidder already placed a bid in this auction"),
+            AuctionError::InvalidClearingOrder => msg!(
+                "Error: Bids are linked and cleared by unit price highest first, then by bid time"
+            ),
+            AuctionError::ClearingIncomplete => msg!("Error: The clearing of the bids is not complete"),
+            AuctionError::NotSettled => msg!("Error: The auction has not been settled yet"),
+            AuctionError::InvalidBuyNowPrice => msg!(
+                "Error: The buy-it-now price needs a single-lot English auction without a hidden reserve and must cover the starting and reserve prices"
+            ),
+            AuctionError::BuyNowUnavailable => {
+                msg!("Error: A bid at or above the buy-it-now price already exists")
+            }
+            AuctionError::InvalidCandleWindow => {
+                msg!("Error: The candle window must fit in the auction and excludes a soft close")
+            }
+            AuctionError::TooManyBids => {
+                msg!("Error: The bidder placed too many bids in the candle window")
+            }
+            AuctionError::InvalidRetractPolicy => {
+                msg!("Error: The bid retraction policy or penalty is invalid")
+            }
+            AuctionError::RetractionForbidden => {
+                msg!("Error: The auction does not allow retracting this bid")
+            }
+            AuctionError::NotAllowlisted => {
+                msg!("Error: The bidder is not on the allowlist of the auction")
+            }
+            AuctionError::AllowlistCapExceeded => {
+                msg!("Error: The bid exceeds the cap of the bidder on the allowlist")
+            }
+            AuctionError::WalletBidCapExceeded => {
+                msg!("Error: The bid exceeds the most a single wallet can bid in this auction")
+            }
+            AuctionError::WalletUnitCapExceeded => {
+                msg!("Error: The bid exceeds the most units a single wallet can win in this auction")
+            }
+            AuctionError::InvalidProgramConfig => {
+                msg!("Error: The program config account is invalid or not initialized")
+            }
+            AuctionError::ProgramPaused => msg!("Error: The program is paused"),
+            AuctionError::OutdatedAuction => {
+                msg!("Error: The auction must be migrated to the latest version first")
+            }
+            AuctionError::InvalidUpgradeAuthority => {
+                msg!("Error: The program upgrade authority is missing or did not sign")
+            }
+            AuctionError::LotsSold => {
+                msg!("Error: A Dutch auction with bought lots cannot be canceled")
+            }
+            AuctionError::BidStillWinning => {
+                msg!("Error: A winning bid cannot be refunded unless the auction is canceled")
+            }
         }
     }
 }
 
 
 fn to_u128(val: u64) -> Result<u128, AuctionError> {
-    val.try_into().map_err(|_| AuctionError::ConversionFailure)
+    Ok(u128::from(val))
 }
 
 fn to_u64(val: u128) -> Result<u64, AuctionError> {
     val.try_into().map_err(|_| AuctionError::ConversionFailure)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::Bank,
+        curve::base::DutchPrice,
+        fees::{AuctionFees, FeeShare, FeeSplits},
+        instruction,
+        rules::{BidRules, RetractRules, SoftClose, WalletCaps},
+    };
+    use solana_program::instruction::{AccountMeta, Instruction};
+
+    const START: UnixTimestamp = 100;
+    const END: UnixTimestamp = 200;
+    const SOL: u64 = 1_000_000_000;
+
+    fn initialize_data() -> InitializeData {
+        InitializeData {
+            fees: AuctionFees {
+                auction_fee_numerator: 1,
+                auction_fee_denominator: 100,
+            },
+            auction_index: 0,
+            start_timestamp: START,
+            end_timestamp: END,
+            max_winners: 1,
+            lot_amount: 1,
+            reserve_price: 0,
+            reserve_commitment: None,
+            bid_rules: BidRules::default(),
+            soft_close: SoftClose::default(),
+            auction_type: AuctionType::English,
+            dutch_price: DutchPrice::default(),
+            reveal_end_timestamp: 0,
+            min_deposit: 0,
+            pricing_rule: PricingRule::FirstPrice,
+            buy_now_price: 0,
+            candle_window: 0,
+            retract_rules: RetractRules::default(),
+            allowlist_root: None,
+            wallet_caps: WalletCaps::default(),
+            fee_splits: FeeSplits::default(),
+        }
+    }
+
+    /// Accounts of an auction in a bank
+    struct TestAuction {
+        key: Pubkey,
+        authority: Pubkey,
+        seller: Pubkey,
+        pool_mint: Pubkey,
+        lot_mint: Pubkey,
+        pool: Pubkey,
+        owner_token: Pubkey,
+        fee_account: Pubkey,
+        seller_lot: Pubkey,
+        lot_vault: Pubkey,
+        fee_splits: FeeSplits,
+        native: bool,
+    }
+
+    /// A bidder wallet and the account it bids from, the wallet itself in a
+    /// native SOL auction
+    struct TestBidder {
+        wallet: Pubkey,
+        token: Pubkey,
+        lot: Pubkey,
+        bid_record: Pubkey,
+    }
+
+    impl TestAuction {
+        /// Accounts of a new auction, before it is initialized
+        fn new(bank: &mut Bank, data: &InitializeData, native: bool) -> Self {
+            let seller = Pubkey::new_unique();
+            bank.airdrop(&seller, SOL);
+            let pool_mint = if native {
+                spl_token::native_mint::id()
+            } else {
+                bank.create_mint()
+            };
+            let lot_mint = bank.create_mint();
+            let key =
+                find_auction_address(&crate::id(), &seller, &lot_mint, data.auction_index).0;
+            let authority = find_authority_address(&crate::id(), &key).0;
+            let owner_token = if native {
+                seller
+            } else {
+                bank.create_token_account(&pool_mint, &seller, 0)
+            };
+            Self {
+                key,
+                authority,
+                seller,
+                pool_mint,
+                lot_mint,
+                pool: bank.create_token_account(&pool_mint, &authority, 0),
+                owner_token,
+                fee_account: bank.create_token_account(&pool_mint, &Pubkey::new_unique(), 0),
+                seller_lot: bank.create_token_account(&lot_mint, &seller, data.lot_amount),
+                lot_vault: bank.create_token_account(&lot_mint, &authority, 0),
+                fee_splits: data.fee_splits.clone(),
+                native,
+            }
+        }
+
+        /// Create and initialize an auction
+        fn create(bank: &mut Bank, data: InitializeData, native: bool) -> Self {
+            let auction = Self::new(bank, &data, native);
+            bank.process(&auction.initialize(data)).unwrap();
+            auction
+        }
+
+        fn initialize(&self, data: InitializeData) -> Instruction {
+            let mut ix = instruction::initialize(
+                &crate::id(),
+                &spl_token::id(),
+                &self.key,
+                &self.owner_token,
+                &self.pool,
+                &self.fee_account,
+                data.fees,
+                data.auction_index,
+                data.start_timestamp,
+                data.end_timestamp,
+                data.max_winners,
+                &self.seller_lot,
+                &self.lot_vault,
+                &self.seller,
+                data.lot_amount,
+                data.reserve_price,
+                data.reserve_commitment,
+                data.bid_rules,
+                data.soft_close,
+                data.auction_type,
+                data.dutch_price,
+                data.reveal_end_timestamp,
+                data.min_deposit,
+                data.pricing_rule,
+                data.buy_now_price,
+                data.candle_window,
+                data.retract_rules,
+                data.allowlist_root,
+                data.wallet_caps,
+                data.fee_splits,
+            )
+            .unwrap();
+            if self.native {
+                ix.accounts.push(AccountMeta::new(self.authority, false));
+            }
+            ix
+        }
+
+        fn state(&self, bank: &mut Bank) -> AuctionV2 {
+            AuctionVersion::unpack_latest(&bank.data(&self.key)).unwrap()
+        }
+
+        fn bidder(&self, bank: &mut Bank, funds: u64) -> TestBidder {
+            let wallet = Pubkey::new_unique();
+            bank.airdrop(&wallet, SOL);
+            let token = if self.native {
+                bank.airdrop(&wallet, funds);
+                wallet
+            } else {
+                bank.create_token_account(&self.pool_mint, &wallet, funds)
+            };
+            TestBidder {
+                wallet,
+                token,
+                lot: bank.create_token_account(&self.lot_mint, &wallet, 0),
+                bid_record: find_bid_record_address(&crate::id(), &self.key, &wallet).0,
+            }
+        }
+
+        fn with_native_accounts(&self, mut ix: Instruction) -> Instruction {
+            if self.native {
+                ix.accounts
+                    .extend(instruction::native_accounts(&crate::id(), &self.key));
+            }
+            ix
+        }
+
+        fn place_bid(
+            &self,
+            bank: &mut Bank,
+            bidder: &TestBidder,
+            bid_amount: u64,
+        ) -> ProgramResult {
+            bank.process(
+                &instruction::place_bid(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.key,
+                    &bidder.token,
+                    &self.pool,
+                    &bidder.wallet,
+                    &bidder.wallet,
+                    &bidder.bid_record,
+                    PlaceBid {
+                        bid_amount,
+                        allowlist_proof: None,
+                    },
+                )
+                .unwrap(),
+            )
+        }
+
+        /// Place a unit bid linked after `previous`, the auction when it
+        /// clears first, and before `next`
+        fn place_unit_bid(
+            &self,
+            bank: &mut Bank,
+            bidder: &TestBidder,
+            quantity: u64,
+            unit_price: u64,
+            previous: &Pubkey,
+            next: Option<&Pubkey>,
+        ) -> ProgramResult {
+            bank.process(
+                &instruction::place_unit_bid(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.key,
+                    &bidder.token,
+                    &self.pool,
+                    &bidder.wallet,
+                    &bidder.wallet,
+                    &bidder.bid_record,
+                    previous,
+                    next,
+                    PlaceUnitBid {
+                        quantity,
+                        unit_price,
+                        allowlist_proof: None,
+                    },
+                )
+                .unwrap(),
+            )
+        }
+
+        fn clear_bids(&self, bank: &mut Bank, bid_records: &[Pubkey]) -> ProgramResult {
+            bank.process(&instruction::clear_bids(&crate::id(), &self.key, bid_records).unwrap())
+        }
+
+        fn buy_now(&self, bank: &mut Bank, bidder: &TestBidder, bid_amount: u64) -> ProgramResult {
+            let ix = instruction::buy_now(
+                &crate::id(),
+                &spl_token::id(),
+                &self.key,
+                &bidder.token,
+                &self.pool,
+                &bidder.wallet,
+                &bidder.wallet,
+                &bidder.bid_record,
+                &self.authority,
+                &self.owner_token,
+                &self.fee_account,
+                &self.lot_vault,
+                &self.seller_lot,
+                &bidder.lot,
+                PlaceBid {
+                    bid_amount,
+                    allowlist_proof: None,
+                },
+            )
+            .unwrap();
+            bank.process(&self.with_native_accounts(ix))
+        }
+
+        fn settle(&self, bank: &mut Bank, winner_pubkeys: &[Pubkey]) -> ProgramResult {
+            let mut ix = instruction::settle(
+                &crate::id(),
+                &spl_token::id(),
+                &self.key,
+                &self.seller,
+                &self.authority,
+                &self.pool,
+                &self.owner_token,
+                &self.fee_account,
+                &self.lot_vault,
+                &self.seller_lot,
+                winner_pubkeys,
+                Settle {
+                    reserve_reveal: None,
+                },
+            )
+            .unwrap();
+            ix.accounts
+                .extend(instruction::fee_share_accounts(&self.fee_splits));
+            bank.process(&self.with_native_accounts(ix))
+        }
+
+        fn claim_refund(&self, bank: &mut Bank, bidder: &TestBidder) -> ProgramResult {
+            let ix = instruction::claim_refund(
+                &crate::id(),
+                &spl_token::id(),
+                &self.key,
+                &self.authority,
+                &self.pool,
+                &bidder.bid_record,
+                &bidder.token,
+            )
+            .unwrap();
+            bank.process(&self.with_native_accounts(ix))
+        }
+
+        fn cancel(&self, bank: &mut Bank) -> ProgramResult {
+            bank.process(
+                &instruction::cancel(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &self.key,
+                    &self.seller,
+                    &self.authority,
+                    &self.lot_vault,
+                    &self.seller_lot,
+                    Cancel { canceled: 1 },
+                )
+                .unwrap(),
+            )
+        }
+    }
+
+    #[test]
+    fn english_bid_settle_and_refund() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(&mut bank, initialize_data(), false);
+        let first = auction.bidder(&mut bank, 1_000);
+        let second = auction.bidder(&mut bank, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &first, 100).unwrap();
+        auction.place_bid(&mut bank, &second, 150).unwrap();
+        assert_eq!(bank.token_balance(&auction.pool), 250);
+        assert_eq!(
+            auction.settle(&mut bank, &[second.lot]),
+            Err(AuctionError::NotEnded.into())
+        );
+
+        bank.clock.unix_timestamp = END + 1;
+        auction.settle(&mut bank, &[second.lot]).unwrap();
+        assert_eq!(bank.token_balance(&second.lot), 1);
+        assert_eq!(bank.token_balance(&auction.lot_vault), 0);
+        assert_eq!(bank.token_balance(&auction.fee_account), 1);
+        assert_eq!(bank.token_balance(&auction.owner_token), 149);
+        assert_eq!(
+            auction.settle(&mut bank, &[second.lot]),
+            Err(AuctionError::AlreadySettled.into())
+        );
+
+        assert_eq!(
+            auction.claim_refund(&mut bank, &second),
+            Err(AuctionError::BidStillWinning.into())
+        );
+        auction.claim_refund(&mut bank, &first).unwrap();
+        assert_eq!(bank.token_balance(&first.token), 1_000);
+        assert_eq!(bank.token_balance(&auction.pool), 0);
+        // a second claim pays nothing
+        auction.claim_refund(&mut bank, &first).unwrap();
+        assert_eq!(bank.token_balance(&first.token), 1_000);
+    }
+
+    #[test]
+    fn reserve_not_met_returns_the_lot_and_refunds_the_bid() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                reserve_price: 500,
+                ..initialize_data()
+            },
+            false,
+        );
+        let bidder = auction.bidder(&mut bank, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &bidder, 100).unwrap();
+        bank.clock.unix_timestamp = END + 1;
+        auction.settle(&mut bank, &[]).unwrap();
+        assert_eq!(bank.token_balance(&auction.seller_lot), 1);
+        assert_eq!(bank.token_balance(&auction.owner_token), 0);
+        assert_eq!(bank.token_balance(&auction.fee_account), 0);
+        assert_eq!(auction.state(&mut bank).status, AuctionStatus::Settled);
+
+        auction.claim_refund(&mut bank, &bidder).unwrap();
+        assert_eq!(bank.token_balance(&bidder.token), 1_000);
+        assert_eq!(bank.token_balance(&bidder.lot), 0);
+    }
+
+    #[test]
+    fn buy_now_ends_and_settles_the_auction() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                buy_now_price: 300,
+                ..initialize_data()
+            },
+            false,
+        );
+        let bidder = auction.bidder(&mut bank, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.buy_now(&mut bank, &bidder, 300).unwrap();
+        let state = auction.state(&mut bank);
+        assert_eq!(state.status, AuctionStatus::Settled);
+        assert_eq!(state.end_timestamp, START + 1);
+        assert_eq!(bank.token_balance(&bidder.lot), 1);
+        assert_eq!(bank.token_balance(&bidder.token), 700);
+        assert_eq!(bank.token_balance(&auction.owner_token), 297);
+        assert_eq!(bank.token_balance(&auction.fee_account), 3);
+    }
+
+    #[test]
+    fn native_sol_payouts_unwrap_to_wallets() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(&mut bank, initialize_data(), true);
+        let first = auction.bidder(&mut bank, 100_000);
+        let second = auction.bidder(&mut bank, 150_000);
+        let seller_lamports = bank.lamports(&auction.seller);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &first, 100_000).unwrap();
+        auction.place_bid(&mut bank, &second, 150_000).unwrap();
+        assert_eq!(bank.token_balance(&auction.pool), 250_000);
+        let first_lamports = bank.lamports(&first.wallet);
+
+        bank.clock.unix_timestamp = END + 1;
+        auction.settle(&mut bank, &[second.lot]).unwrap();
+        assert_eq!(bank.lamports(&auction.seller), seller_lamports + 148_500);
+        assert_eq!(bank.token_balance(&auction.fee_account), 1_500);
+        assert_eq!(bank.token_balance(&second.lot), 1);
+
+        auction.claim_refund(&mut bank, &first).unwrap();
+        assert_eq!(bank.lamports(&first.wallet), first_lamports + 100_000);
+        assert_eq!(bank.token_balance(&auction.pool), 0);
+        let unwrap = find_unwrap_address(&crate::id(), &auction.key).0;
+        assert_eq!(bank.lamports(&unwrap), 0);
+    }
+
+    #[test]
+    fn retracted_leader_falls_back_to_the_standby_bid() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                retract_rules: RetractRules {
+                    policy: RetractPolicy::Penalty,
+                    penalty_bps: 1_000,
+                    penalty_recipient: PenaltyRecipient::Seller,
+                },
+                ..initialize_data()
+            },
+            false,
+        );
+        let first = auction.bidder(&mut bank, 1_000);
+        let second = auction.bidder(&mut bank, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &first, 100).unwrap();
+        auction.place_bid(&mut bank, &second, 150).unwrap();
+        bank.process(
+            &instruction::retract_bid(
+                &crate::id(),
+                &spl_token::id(),
+                &auction.key,
+                &auction.authority,
+                &auction.pool,
+                &second.bid_record,
+                &second.token,
+                &second.wallet,
+                Some(&auction.owner_token),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(bank.token_balance(&second.token), 985);
+        assert_eq!(bank.token_balance(&auction.owner_token), 15);
+        let state = auction.state(&mut bank);
+        assert_eq!(state.highest_bid().map(|w| w.bidder), Some(first.wallet));
+        assert!(state.standby.is_empty());
+
+        bank.clock.unix_timestamp = END + 1;
+        auction.settle(&mut bank, &[first.lot]).unwrap();
+        assert_eq!(bank.token_balance(&first.lot), 1);
+        assert_eq!(bank.token_balance(&auction.owner_token), 15 + 99);
+    }
+
+    #[test]
+    fn multi_unit_bids_clear_and_claim_their_fill() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                auction_type: AuctionType::MultiUnit,
+                lot_amount: 10,
+                ..initialize_data()
+            },
+            false,
+        );
+        let first = auction.bidder(&mut bank, 1_000);
+        let second = auction.bidder(&mut bank, 1_000);
+        bank.clock.unix_timestamp = START + 1;
+        auction
+            .place_unit_bid(&mut bank, &first, 6, 5, &auction.key, None)
+            .unwrap();
+        auction
+            .place_unit_bid(&mut bank, &second, 6, 4, &first.bid_record, None)
+            .unwrap();
+        assert_eq!(bank.token_balance(&auction.pool), 54);
+
+        bank.clock.unix_timestamp = END + 1;
+        auction
+            .clear_bids(&mut bank, &[first.bid_record, second.bid_record])
+            .unwrap();
+        let state = auction.state(&mut bank);
+        assert_eq!(state.clearing_price, 4);
+        assert_eq!(state.units_allocated, 10);
+        auction.settle(&mut bank, &[]).unwrap();
+        assert_eq!(bank.token_balance(&auction.owner_token), 39);
+        assert_eq!(bank.token_balance(&auction.fee_account), 1);
+
+        // both pay the clearing price of 4 for the units they got
+        for (bidder, filled, balance) in [(&first, 6, 976), (&second, 4, 984)] {
+            bank.process(
+                &instruction::claim_fill(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &auction.key,
+                    &auction.authority,
+                    &auction.pool,
+                    &auction.lot_vault,
+                    &bidder.bid_record,
+                    &bidder.token,
+                    &bidder.lot,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(bank.token_balance(&bidder.lot), filled);
+            assert_eq!(bank.token_balance(&bidder.token), balance);
+        }
+        assert_eq!(bank.token_balance(&auction.lot_vault), 0);
+    }
+
+    /// A V1 auction at `key`, and the migration instruction of a new payer
+    fn v1_auction(bank: &mut Bank, key: Pubkey) -> (Pubkey, Instruction) {
+        let payer = Pubkey::new_unique();
+        bank.airdrop(&payer, SOL);
+        let pool_mint = bank.create_mint();
+        let authority = find_authority_address(&crate::id(), &key).0;
+        let token = bank.create_token_account(&pool_mint, &authority, 0);
+        let v1 = AuctionV1 {
+            is_initialized: true,
+            token_program_id: spl_token::id(),
+            token,
+            start_timestamp: START,
+            end_timestamp: END,
+            ..AuctionV1::default()
+        };
+        let mut data = vec![0; 1 + AuctionV1::LEN];
+        AuctionVersion::pack(AuctionVersion::AuctionV1(v1), &mut data).unwrap();
+        bank.set_account(key, bank.rent.minimum_balance(data.len()), data, crate::id());
+        (
+            token,
+            instruction::migrate_auction(&crate::id(), &key, &payer, &token).unwrap(),
+        )
+    }
+
+    #[test]
+    fn v1_auction_migrates_in_two_steps() {
+        let mut bank = Bank::default();
+        let key = Pubkey::new_unique();
+        let authority = find_authority_address(&crate::id(), &key).0;
+        let (token, migrate) = v1_auction(&mut bank, key);
+
+        bank.process(&migrate).unwrap();
+        assert_eq!(bank.lamports(&key), 0);
+        let migration = find_migration_address(&crate::id(), &key).0;
+        assert_eq!(bank.owner(&migration), crate::id());
+
+        bank.process(&migrate).unwrap();
+        assert_eq!(bank.lamports(&migration), 0);
+        assert_eq!(bank.data(&key).len(), AuctionVersion::LATEST_LEN);
+        let auction = AuctionVersion::unpack_latest(&bank.data(&key)).unwrap();
+        assert_eq!(auction.authority, authority);
+        assert_eq!(auction.token, token);
+        assert_eq!(auction.end_timestamp, END);
+        // a migrated auction is left as it is
+        bank.process(&migrate).unwrap();
+    }
+
+    #[test]
+    fn cancel_is_refused_once_over_or_a_dutch_lot_is_bought() {
+        let mut bank = Bank::default();
+        let english = TestAuction::create(&mut bank, initialize_data(), false);
+        bank.clock.unix_timestamp = END + 1;
+        assert_eq!(english.cancel(&mut bank), Err(AuctionError::Ended.into()));
+
+        let dutch_data = |max_winners| InitializeData {
+            auction_type: AuctionType::Dutch,
+            dutch_price: DutchPrice {
+                start_price: 100,
+                floor_price: 50,
+                ..DutchPrice::default()
+            },
+            max_winners,
+            lot_amount: max_winners as u64,
+            ..initialize_data()
+        };
+        bank.clock.unix_timestamp = START + 1;
+        let sold_out = TestAuction::create(&mut bank, dutch_data(1), false);
+        let bidder = sold_out.bidder(&mut bank, 1_000);
+        sold_out.place_bid(&mut bank, &bidder, 100).unwrap();
+        assert_eq!(sold_out.cancel(&mut bank), Err(AuctionError::Ended.into()));
+
+        let dutch = TestAuction::create(&mut bank, dutch_data(2), false);
+        let bidder = dutch.bidder(&mut bank, 1_000);
+        dutch.place_bid(&mut bank, &bidder, 100).unwrap();
+        assert_eq!(dutch.cancel(&mut bank), Err(AuctionError::LotsSold.into()));
+        assert_eq!(bank.token_balance(&dutch.lot_vault), 2);
+
+        // a sealed auction is not over until its reveal phase is
+        let sealed = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                auction_type: AuctionType::Sealed,
+                reveal_end_timestamp: END + 100,
+                ..initialize_data()
+            },
+            false,
+        );
+        bank.clock.unix_timestamp = END + 1;
+        sealed.cancel(&mut bank).unwrap();
+        assert_eq!(bank.token_balance(&sealed.seller_lot), 1);
+        assert_eq!(sealed.state(&mut bank).status, AuctionStatus::Canceled);
+    }
+
+    #[test]
+    fn unit_bids_keep_the_clearing_order() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                auction_type: AuctionType::MultiUnit,
+                lot_amount: 10,
+                ..initialize_data()
+            },
+            false,
+        );
+        let first = auction.bidder(&mut bank, 1_000);
+        let second = auction.bidder(&mut bank, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction
+            .place_unit_bid(&mut bank, &first, 6, 5, &auction.key, None)
+            .unwrap();
+        // a higher unit price clears before the first bid, not after it
+        assert_eq!(
+            auction.place_unit_bid(&mut bank, &second, 6, 6, &first.bid_record, None),
+            Err(AuctionError::InvalidClearingOrder.into())
+        );
+        assert_eq!(
+            auction.place_unit_bid(&mut bank, &second, 6, 6, &auction.key, Some(&second.token)),
+            Err(AuctionError::InvalidClearingOrder.into())
+        );
+        auction
+            .place_unit_bid(&mut bank, &second, 6, 6, &auction.key, Some(&first.bid_record))
+            .unwrap();
+
+        bank.clock.unix_timestamp = END + 1;
+        assert_eq!(
+            auction.clear_bids(&mut bank, &[first.bid_record]),
+            Err(AuctionError::InvalidClearingOrder.into())
+        );
+        auction
+            .clear_bids(&mut bank, &[second.bid_record, first.bid_record])
+            .unwrap();
+        let state = auction.state(&mut bank);
+        assert_eq!(state.clearing_price, 5);
+        assert_eq!(state.units_allocated, 10);
+    }
+
+    #[test]
+    fn buy_now_charges_the_price_and_needs_a_public_reserve() {
+        let mut bank = Bank::default();
+        let data = InitializeData {
+            buy_now_price: 300,
+            ..initialize_data()
+        };
+        let hidden = TestAuction::new(&mut bank, &data, false);
+        assert_eq!(
+            bank.process(&hidden.initialize(InitializeData {
+                reserve_commitment: Some([7; 32]),
+                ..data.clone()
+            })),
+            Err(AuctionError::InvalidBuyNowPrice.into())
+        );
+
+        let auction = TestAuction::create(&mut bank, data, false);
+        let outbid = auction.bidder(&mut bank, 1_000);
+        let buyer = auction.bidder(&mut bank, 1_000);
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &outbid, 100).unwrap();
+        auction.buy_now(&mut bank, &buyer, 500).unwrap();
+        assert_eq!(bank.token_balance(&buyer.token), 700);
+        assert_eq!(bank.token_balance(&buyer.lot), 1);
+        assert_eq!(bank.token_balance(&auction.pool), 100);
+        assert_eq!(auction.state(&mut bank).highest_bid().map(|w| w.amount), Some(300));
+
+        auction.claim_refund(&mut bank, &outbid).unwrap();
+        assert_eq!(bank.token_balance(&outbid.token), 1_000);
+        assert_eq!(bank.token_balance(&auction.pool), 0);
+    }
+
+    #[test]
+    fn settle_pays_the_royalty_and_marketplace_shares() {
+        let mut bank = Bank::default();
+        let mut auction = TestAuction::new(&mut bank, &initialize_data(), false);
+        let royalty = bank.create_token_account(&auction.pool_mint, &Pubkey::new_unique(), 0);
+        let marketplace = bank.create_token_account(&auction.pool_mint, &Pubkey::new_unique(), 0);
+        auction.fee_splits = FeeSplits {
+            royalty: FeeShare {
+                bps: 500,
+                account: royalty,
+            },
+            marketplace: FeeShare {
+                bps: 250,
+                account: marketplace,
+            },
+        };
+        bank.process(&auction.initialize(InitializeData {
+            fee_splits: auction.fee_splits.clone(),
+            ..initialize_data()
+        }))
+        .unwrap();
+        let bidder = auction.bidder(&mut bank, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &bidder, 999).unwrap();
+        bank.clock.unix_timestamp = END + 1;
+        auction.settle(&mut bank, &[bidder.lot]).unwrap();
+        // every share rounds down and the seller gets the dust
+        assert_eq!(bank.token_balance(&auction.fee_account), 9);
+        assert_eq!(bank.token_balance(&royalty), 49);
+        assert_eq!(bank.token_balance(&marketplace), 24);
+        assert_eq!(bank.token_balance(&auction.owner_token), 917);
+        assert_eq!(bank.token_balance(&auction.pool), 0);
+    }
+
+    #[test]
+    fn native_payout_takes_over_a_funded_unwrap_account() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(&mut bank, initialize_data(), true);
+        let bidder = auction.bidder(&mut bank, 100_000);
+        let seller_lamports = bank.lamports(&auction.seller);
+        let authority_lamports = bank.lamports(&auction.authority);
+        let unwrap = find_unwrap_address(&crate::id(), &auction.key).0;
+        bank.airdrop(&unwrap, 1_000);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &bidder, 100_000).unwrap();
+        bank.clock.unix_timestamp = END + 1;
+        auction.settle(&mut bank, &[bidder.lot]).unwrap();
+        assert_eq!(bank.lamports(&auction.seller), seller_lamports + 99_000);
+        assert_eq!(bank.token_balance(&bidder.lot), 1);
+        // the lamports sent ahead are swept to the authority with the rent
+        assert_eq!(bank.lamports(&unwrap), 0);
+        assert_eq!(bank.lamports(&auction.authority), authority_lamports + 1_000);
+    }
+
+    #[test]
+    fn first_bid_takes_over_a_funded_bid_record() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(&mut bank, initialize_data(), false);
+        let bidder = auction.bidder(&mut bank, 1_000);
+        bank.airdrop(&bidder.bid_record, 1_000);
+        let wallet_lamports = bank.lamports(&bidder.wallet);
+
+        bank.clock.unix_timestamp = START + 1;
+        auction.place_bid(&mut bank, &bidder, 100).unwrap();
+        assert_eq!(bank.owner(&bidder.bid_record), crate::id());
+        let rent = bank.rent.minimum_balance(BidRecord::SPACE);
+        assert_eq!(bank.lamports(&bidder.bid_record), rent);
+        assert_eq!(bank.lamports(&bidder.wallet), wallet_lamports - (rent - 1_000));
+        assert_eq!(
+            BidRecord::unpack_versioned(&bank.data(&bidder.bid_record)).unwrap().amount,
+            100
+        );
+    }
+
+    #[test]
+    fn migration_takes_over_funded_accounts() {
+        let mut bank = Bank::default();
+        let key = Pubkey::new_unique();
+        let (_, migrate) = v1_auction(&mut bank, key);
+        let migration = find_migration_address(&crate::id(), &key).0;
+        bank.airdrop(&migration, 1_000);
+
+        bank.process(&migrate).unwrap();
+        assert_eq!(bank.owner(&migration), crate::id());
+        let rent = bank.rent.minimum_balance(AuctionVersion::LATEST_LEN);
+        assert_eq!(bank.lamports(&migration), rent);
+
+        // lamports sent to the closed auction account do not block the
+        // second step
+        bank.airdrop(&key, 1_000);
+        bank.process(&migrate).unwrap();
+        assert_eq!(bank.owner(&key), crate::id());
+        assert_eq!(bank.lamports(&key), rent);
+        assert!(AuctionVersion::unpack_latest(&bank.data(&key)).is_ok());
+    }
+
+    #[test]
+    fn config_takes_over_a_funded_address() {
+        let mut bank = Bank::default();
+        let payer = Pubkey::new_unique();
+        bank.airdrop(&payer, SOL);
+        let upgrade_authority = Pubkey::new_unique();
+        let (program_data, _) =
+            Pubkey::find_program_address(&[crate::id().as_ref()], &bpf_loader_upgradeable::id());
+        let mut data = vec![0; 45];
+        data[..4].copy_from_slice(&3u32.to_le_bytes());
+        data[12] = 1;
+        data[13..].copy_from_slice(upgrade_authority.as_ref());
+        bank.set_account(program_data, 1, data, bpf_loader_upgradeable::id());
+        let config = find_config_address(&crate::id()).0;
+        bank.airdrop(&config, 1_000);
+
+        let fees = initialize_data().fees;
+        bank.process(
+            &instruction::initialize_config(
+                &crate::id(),
+                &payer,
+                &upgrade_authority,
+                ConfigData {
+                    owner: payer,
+                    min_fees: fees.clone(),
+                    max_fees: fees,
+                    fee_owner: None,
+                    paused: false,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(bank.owner(&config), crate::id());
+        assert_eq!(
+            ProgramConfig::unpack_versioned(&bank.data(&config)).unwrap().owner,
+            payer
+        );
+    }
+
+    #[test]
+    fn initialize_takes_over_a_funded_auction_address() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::new(&mut bank, &initialize_data(), false);
+        bank.airdrop(&auction.key, 1_000);
+        bank.process(&auction.initialize(initialize_data())).unwrap();
+        assert_eq!(bank.owner(&auction.key), crate::id());
+        assert_eq!(
+            bank.lamports(&auction.key),
+            bank.rent.minimum_balance(AuctionVersion::LATEST_LEN)
+        );
+        assert_eq!(auction.state(&mut bank).status, AuctionStatus::Active);
+        assert_eq!(bank.token_balance(&auction.lot_vault), 1);
+        assert_eq!(
+            bank.process(&auction.initialize(initialize_data())),
+            Err(AuctionError::AlreadyInUse.into())
+        );
+    }
+
+    #[test]
+    fn copied_commitment_does_not_reveal_for_another_bidder() {
+        let mut bank = Bank::default();
+        let auction = TestAuction::create(
+            &mut bank,
+            InitializeData {
+                auction_type: AuctionType::Sealed,
+                reveal_end_timestamp: END + 100,
+                ..initialize_data()
+            },
+            false,
+        );
+        let bidder = auction.bidder(&mut bank, 1_000);
+        let copier = auction.bidder(&mut bank, 1_000);
+        let salt = [3; 32];
+        let commitment = hash_commitment(&auction.key, &bidder.wallet, 200, &salt);
+
+        bank.clock.unix_timestamp = START + 1;
+        for sealed_bidder in [&bidder, &copier] {
+            bank.process(
+                &instruction::commit_bid(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &auction.key,
+                    &sealed_bidder.token,
+                    &auction.pool,
+                    &sealed_bidder.wallet,
+                    &sealed_bidder.wallet,
+                    &sealed_bidder.bid_record,
+                    CommitBid {
+                        commitment,
+                        deposit: 200,
+                        allowlist_proof: None,
+                    },
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        }
+
+        bank.clock.unix_timestamp = END + 1;
+        let reveal = |bank: &mut Bank, sealed_bidder: &TestBidder| {
+            bank.process(
+                &instruction::reveal_bid(
+                    &crate::id(),
+                    &spl_token::id(),
+                    &auction.key,
+                    &sealed_bidder.token,
+                    &auction.pool,
+                    &sealed_bidder.wallet,
+                    &sealed_bidder.wallet,
+                    &sealed_bidder.bid_record,
+                    RevealBid { amount: 200, salt },
+                )
+                .unwrap(),
+            )
+        };
+        assert_eq!(
+            reveal(&mut bank, &copier),
+            Err(AuctionError::InvalidCommitment.into())
+        );
+        reveal(&mut bank, &bidder).unwrap();
+        assert_eq!(
+            auction.state(&mut bank).highest_bid().map(|w| w.bidder),
+            Some(bidder.wallet)
+        );
+    }
+}
@@ -0,0 +1,435 @@
+//! Bid increment rules
+
+use crate::error::AuctionError;
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::{
+    clock::UnixTimestamp,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+};
+use std::convert::TryFrom;
+
+/// Basis points in one whole
+pub const BASIS_POINTS: u128 = 10_000;
+
+/// Minimum price and increments a bid must satisfy
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct BidRules {
+    /// Opening price, the lowest total bid accepted
+    pub starting_price: u64,
+    /// Absolute amount a bid must add over the bid it beats
+    pub min_increment: u64,
+    /// Step over the bid it beats, in basis points of that bid
+    pub min_increment_bps: u16,
+}
+
+impl BidRules {
+    /// Lowest total bid that beats `bid_to_beat`
+    pub fn minimum_bid(&self, bid_to_beat: Option<u64>) -> Option<u64> {
+        match bid_to_beat {
+            None => Some(self.starting_price),
+            Some(bid_to_beat) => {
+                let step = u128::from(bid_to_beat)
+                    .checked_mul(u128::from(self.min_increment_bps))?
+                    .checked_add(BASIS_POINTS - 1)?
+                    / BASIS_POINTS;
+                // a bid always has to exceed the one it beats
+                let increment = step.max(u128::from(self.min_increment)).max(1);
+                let minimum = u128::from(bid_to_beat).checked_add(increment)?;
+                let minimum = minimum.max(u128::from(self.starting_price));
+                u64::try_from(minimum).ok()
+            }
+        }
+    }
+
+    /// Check a bidder's new total against the starting price and increments
+    pub fn check_bid(&self, bid_to_beat: Option<u64>, total_bid: u64) -> Result<(), AuctionError> {
+        if total_bid < self.starting_price {
+            return Err(AuctionError::BidBelowStartingPrice);
+        }
+        let minimum = self
+            .minimum_bid(bid_to_beat)
+            .ok_or(AuctionError::CalculationFailure)?;
+        if total_bid < minimum {
+            return Err(AuctionError::BidIncrementTooSmall);
+        }
+        Ok(())
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for BidRules {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Sealed for BidRules {}
+impl Pack for BidRules {
+    const LEN: usize = 18;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 18];
+        let (starting_price, min_increment, min_increment_bps) = mut_array_refs![output, 8, 8, 2];
+        *starting_price = self.starting_price.to_le_bytes();
+        *min_increment = self.min_increment.to_le_bytes();
+        *min_increment_bps = self.min_increment_bps.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<BidRules, ProgramError> {
+        let input = array_ref![input, 0, 18];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (starting_price, min_increment, min_increment_bps) = array_refs![input, 8, 8, 2];
+        Ok(Self {
+            starting_price: u64::from_le_bytes(*starting_price),
+            min_increment: u64::from_le_bytes(*min_increment),
+            min_increment_bps: u16::from_le_bytes(*min_increment_bps),
+        })
+    }
+}
+
+/// Soft close of an English auction: a bid landing shortly before the end
+/// pushes the end out, so that other bidders get time to answer
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct SoftClose {
+    /// Seconds before the end in which a bid extends the auction, 0 disables
+    pub extension_window: UnixTimestamp,
+    /// Seconds the auction stays open after an extending bid
+    pub extension_duration: UnixTimestamp,
+    /// Cap on the total extension in seconds, 0 for no cap
+    pub max_extension: UnixTimestamp,
+}
+
+impl SoftClose {
+    /// New end timestamp and total extension after a bid at `now`, if the
+    /// bid extends the auction
+    pub fn extend(
+        &self,
+        end_timestamp: UnixTimestamp,
+        total_extension: UnixTimestamp,
+        now: UnixTimestamp,
+    ) -> Option<(UnixTimestamp, UnixTimestamp)> {
+        if self.extension_window == 0 || now < end_timestamp.saturating_sub(self.extension_window) {
+            return None;
+        }
+        let mut extension = now
+            .saturating_add(self.extension_duration)
+            .saturating_sub(end_timestamp);
+        if self.max_extension > 0 {
+            extension = extension.min(self.max_extension.saturating_sub(total_extension));
+        }
+        if extension <= 0 {
+            return None;
+        }
+        Some((
+            end_timestamp.checked_add(extension)?,
+            total_extension.checked_add(extension)?,
+        ))
+    }
+
+    /// Validate that the window and durations are not negative
+    pub fn validate(&self) -> Result<(), AuctionError> {
+        if self.extension_window < 0 || self.extension_duration < 0 || self.max_extension < 0 {
+            Err(AuctionError::InvalidSoftClose)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for SoftClose {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Sealed for SoftClose {}
+impl Pack for SoftClose {
+    const LEN: usize = 24;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 24];
+        let (extension_window, extension_duration, max_extension) = mut_array_refs![output, 8, 8, 8];
+        *extension_window = self.extension_window.to_le_bytes();
+        *extension_duration = self.extension_duration.to_le_bytes();
+        *max_extension = self.max_extension.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<SoftClose, ProgramError> {
+        let input = array_ref![input, 0, 24];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (extension_window, extension_duration, max_extension) = array_refs![input, 8, 8, 8];
+        Ok(Self {
+            extension_window: i64::from_le_bytes(*extension_window),
+            extension_duration: i64::from_le_bytes(*extension_duration),
+            max_extension: i64::from_le_bytes(*max_extension),
+        })
+    }
+}
+
+/// Who may take back a bid before the end of an English auction
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub enum RetractPolicy {
+    /// Bids cannot be retracted
+    #[default]
+    Forbidden = 0,
+    /// Bids outside the winning set can be retracted
+    NonLeading = 1,
+    /// Any bid can be retracted, less a penalty
+    Penalty = 2,
+}
+
+impl TryFrom<u8> for RetractPolicy {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RetractPolicy::Forbidden),
+            1 => Ok(RetractPolicy::NonLeading),
+            2 => Ok(RetractPolicy::Penalty),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Account the penalty of a retracted bid is paid to
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub enum PenaltyRecipient {
+    /// The owner token account of the seller
+    #[default]
+    Seller = 0,
+    /// The fee account of the auction
+    FeeAccount = 1,
+}
+
+impl TryFrom<u8> for PenaltyRecipient {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PenaltyRecipient::Seller),
+            1 => Ok(PenaltyRecipient::FeeAccount),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Retraction of bids before the end of an English auction
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct RetractRules {
+    /// Which bids can be retracted
+    pub policy: RetractPolicy,
+    /// Share of a retracted bid kept as a penalty, in basis points
+    pub penalty_bps: u16,
+    /// Account the penalty is paid to
+    pub penalty_recipient: PenaltyRecipient,
+}
+
+impl RetractRules {
+    /// Check that a bid can be retracted, `leading` when it is in the
+    /// winning set
+    pub fn check_retract(&self, leading: bool) -> Result<(), AuctionError> {
+        match self.policy {
+            RetractPolicy::Forbidden => Err(AuctionError::RetractionForbidden),
+            RetractPolicy::NonLeading if leading => Err(AuctionError::RetractionForbidden),
+            _ => Ok(()),
+        }
+    }
+
+    /// Part of a retracted bid of `amount` kept as a penalty, rounded down
+    pub fn penalty(&self, amount: u64) -> Option<u64> {
+        if self.policy != RetractPolicy::Penalty {
+            return Some(0);
+        }
+        let penalty = u128::from(amount).checked_mul(u128::from(self.penalty_bps))? / BASIS_POINTS;
+        u64::try_from(penalty).ok()
+    }
+
+    /// Validate that only the penalty policy sets a penalty, of at most the
+    /// whole bid
+    pub fn validate(&self) -> Result<(), AuctionError> {
+        let valid = match self.policy {
+            RetractPolicy::Penalty => {
+                self.penalty_bps > 0 && u128::from(self.penalty_bps) <= BASIS_POINTS
+            }
+            _ => self.penalty_bps == 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(AuctionError::InvalidRetractPolicy)
+        }
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for RetractRules {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Sealed for RetractRules {}
+impl Pack for RetractRules {
+    const LEN: usize = 4;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 4];
+        let (policy, penalty_bps, penalty_recipient) = mut_array_refs![output, 1, 2, 1];
+        policy[0] = self.policy as u8;
+        *penalty_bps = self.penalty_bps.to_le_bytes();
+        penalty_recipient[0] = self.penalty_recipient as u8;
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<RetractRules, ProgramError> {
+        let input = array_ref![input, 0, 4];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (policy, penalty_bps, penalty_recipient) = array_refs![input, 1, 2, 1];
+        Ok(Self {
+            policy: RetractPolicy::try_from(policy[0])?,
+            penalty_bps: u16::from_le_bytes(*penalty_bps),
+            penalty_recipient: PenaltyRecipient::try_from(penalty_recipient[0])?,
+        })
+    }
+}
+
+/// Limits on what a single wallet can commit to and win in one auction
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct WalletCaps {
+    /// Most a wallet can escrow in total, 0 for no cap
+    pub max_bid_per_wallet: u64,
+    /// Most units of the lot a wallet can win, 0 for no cap
+    pub max_units_per_wallet: u64,
+}
+
+impl WalletCaps {
+    /// Check the total escrowed by a wallet and the units it bids for
+    pub fn check(&self, total_bid: u64, units: u64) -> Result<(), AuctionError> {
+        if self.max_bid_per_wallet > 0 && total_bid > self.max_bid_per_wallet {
+            return Err(AuctionError::WalletBidCapExceeded);
+        }
+        if self.max_units_per_wallet > 0 && units > self.max_units_per_wallet {
+            return Err(AuctionError::WalletUnitCapExceeded);
+        }
+        Ok(())
+    }
+}
+
+/// IsInitialized is required to use `Pack::pack` and `Pack::unpack`
+impl IsInitialized for WalletCaps {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Sealed for WalletCaps {}
+impl Pack for WalletCaps {
+    const LEN: usize = 16;
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 16];
+        let (max_bid_per_wallet, max_units_per_wallet) = mut_array_refs![output, 8, 8];
+        *max_bid_per_wallet = self.max_bid_per_wallet.to_le_bytes();
+        *max_units_per_wallet = self.max_units_per_wallet.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<WalletCaps, ProgramError> {
+        let input = array_ref![input, 0, 16];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (max_bid_per_wallet, max_units_per_wallet) = array_refs![input, 8, 8];
+        Ok(Self {
+            max_bid_per_wallet: u64::from_le_bytes(*max_bid_per_wallet),
+            max_units_per_wallet: u64::from_le_bytes(*max_units_per_wallet),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimum_bid_takes_the_larger_step() {
+        let rules = BidRules {
+            starting_price: 100,
+            min_increment: 10,
+            min_increment_bps: 500,
+        };
+        assert_eq!(rules.minimum_bid(None), Some(100));
+        // 5% of 100 is below the absolute tick
+        assert_eq!(rules.minimum_bid(Some(100)), Some(110));
+        // 5% of 1_001 is above it, and rounds up
+        assert_eq!(rules.minimum_bid(Some(1_001)), Some(1_052));
+
+        assert_eq!(rules.check_bid(None, 99), Err(AuctionError::BidBelowStartingPrice));
+        assert_eq!(rules.check_bid(Some(100), 109), Err(AuctionError::BidIncrementTooSmall));
+        assert_eq!(rules.check_bid(Some(100), 110), Ok(()));
+    }
+
+    #[test]
+    fn default_rules_only_require_a_higher_bid() {
+        let rules = BidRules::default();
+        assert_eq!(rules.minimum_bid(Some(7)), Some(8));
+        assert_eq!(rules.minimum_bid(Some(u64::MAX)), None);
+    }
+
+    #[test]
+    fn late_bids_extend_up_to_the_cap() {
+        let soft_close = SoftClose {
+            extension_window: 60,
+            extension_duration: 120,
+            max_extension: 100,
+        };
+        // outside the window
+        assert_eq!(soft_close.extend(1_000, 0, 900), None);
+        // inside the window, the auction stays open 120s after the bid
+        assert_eq!(soft_close.extend(1_000, 0, 950), Some((1_070, 70)));
+        // the cap limits the total extension
+        assert_eq!(soft_close.extend(1_070, 70, 1_060), Some((1_100, 100)));
+        assert_eq!(soft_close.extend(1_100, 100, 1_090), None);
+        assert_eq!(SoftClose::default().extend(1_000, 0, 999), None);
+    }
+
+    #[test]
+    fn retraction_follows_the_policy() {
+        let non_leading = RetractRules {
+            policy: RetractPolicy::NonLeading,
+            ..RetractRules::default()
+        };
+        assert_eq!(non_leading.check_retract(false), Ok(()));
+        assert_eq!(non_leading.check_retract(true), Err(AuctionError::RetractionForbidden));
+        assert_eq!(non_leading.penalty(1_000), Some(0));
+        assert_eq!(
+            RetractRules::default().check_retract(false),
+            Err(AuctionError::RetractionForbidden)
+        );
+
+        let penalty = RetractRules {
+            policy: RetractPolicy::Penalty,
+            penalty_bps: 250,
+            penalty_recipient: PenaltyRecipient::FeeAccount,
+        };
+        assert_eq!(penalty.check_retract(true), Ok(()));
+        // 2.5% of 1_999 rounds down
+        assert_eq!(penalty.penalty(1_999), Some(49));
+        assert_eq!(penalty.validate(), Ok(()));
+        assert_eq!(
+            RetractRules { penalty_bps: 10_001, ..penalty.clone() }.validate(),
+            Err(AuctionError::InvalidRetractPolicy)
+        );
+        assert_eq!(
+            RetractRules { penalty_bps: 1, ..non_leading }.validate(),
+            Err(AuctionError::InvalidRetractPolicy)
+        );
+    }
+
+    #[test]
+    fn wallet_caps_have_distinct_errors() {
+        let caps = WalletCaps {
+            max_bid_per_wallet: 1_000,
+            max_units_per_wallet: 5,
+        };
+        assert_eq!(caps.check(1_000, 5), Ok(()));
+        assert_eq!(caps.check(1_001, 5), Err(AuctionError::WalletBidCapExceeded));
+        assert_eq!(caps.check(1_000, 6), Err(AuctionError::WalletUnitCapExceeded));
+        assert_eq!(WalletCaps::default().check(u64::MAX, u64::MAX), Ok(()));
+    }
+}
@@ -0,0 +1,130 @@
+//! Borsh schema of the instructions and accounts, published for clients
+//!
+//! The JSON rendering is checked in at `src/auction/client/schema.json`, run
+//! the tests with `UPDATE_SCHEMA=1` to regenerate it after a layout change.
+
+use crate::{
+    instruction::{AuctionInstruction, BORSH_INSTRUCTION_TAG},
+    state::{AuctionV2, BidRecord, ProgramConfig, VersionedAccount, BORSH_AUCTION_VERSION},
+};
+use borsh::{
+    schema::{BorshSchemaContainer, Definition, Fields},
+    BorshSchema,
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Schema of every type a client decodes, with the tag byte written before
+/// it. Each root has its own tag, so the tag identifies the root.
+pub fn roots() -> Vec<(u8, BorshSchemaContainer)> {
+    vec![
+        (BORSH_INSTRUCTION_TAG, AuctionInstruction::schema_container()),
+        (BORSH_AUCTION_VERSION, AuctionV2::schema_container()),
+        (BidRecord::TAG, BidRecord::schema_container()),
+        (ProgramConfig::TAG, ProgramConfig::schema_container()),
+    ]
+}
+
+/// Render the schema as JSON, definitions sorted so the output is stable
+pub fn schema_json() -> String {
+    let mut definitions = BTreeMap::new();
+    let mut roots_json = vec![];
+    for (tag, container) in roots() {
+        roots_json.push(format!(
+            "    {{ \"name\": {}, \"tag\": {} }}",
+            quote(&container.declaration),
+            tag
+        ));
+        for (declaration, definition) in container.definitions {
+            definitions.insert(declaration, definition_json(&definition));
+        }
+    }
+    let mut json = String::from("{\n  \"roots\": [\n");
+    json.push_str(&roots_json.join(",\n"));
+    json.push_str("\n  ],\n  \"definitions\": {\n");
+    let definitions: Vec<_> = definitions
+        .iter()
+        .map(|(declaration, definition)| format!("    {}: {}", quote(declaration), definition))
+        .collect();
+    json.push_str(&definitions.join(",\n"));
+    json.push_str("\n  }\n}\n");
+    json
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn list(names: &[String]) -> String {
+    let names: Vec<_> = names.iter().map(|name| quote(name)).collect();
+    format!("[{}]", names.join(", "))
+}
+
+fn pairs(pairs: &[(String, String)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(name, declaration)| format!("[{}, {}]", quote(name), quote(declaration)))
+        .collect();
+    format!("[{}]", pairs.join(", "))
+}
+
+fn definition_json(definition: &Definition) -> String {
+    let mut json = String::new();
+    match definition {
+        Definition::Array { length, elements } => write!(
+            json,
+            "{{ \"kind\": \"array\", \"length\": {}, \"elements\": {} }}",
+            length,
+            quote(elements)
+        ),
+        Definition::Sequence { elements } => write!(
+            json,
+            "{{ \"kind\": \"sequence\", \"elements\": {} }}",
+            quote(elements)
+        ),
+        Definition::Tuple { elements }
+        | Definition::Struct {
+            fields: Fields::UnnamedFields(elements),
+        } => write!(json, "{{ \"kind\": \"tuple\", \"elements\": {} }}", list(elements)),
+        Definition::Enum { variants } => write!(
+            json,
+            "{{ \"kind\": \"enum\", \"variants\": {} }}",
+            pairs(variants)
+        ),
+        Definition::Struct {
+            fields: Fields::NamedFields(fields),
+        } => write!(json, "{{ \"kind\": \"struct\", \"fields\": {} }}", pairs(fields)),
+        Definition::Struct {
+            fields: Fields::Empty,
+        } => write!(json, "{{ \"kind\": \"struct\", \"fields\": [] }}"),
+    }
+    .unwrap();
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf};
+
+    #[test]
+    fn published_schema_is_current() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../client/schema.json");
+        let schema = schema_json();
+        if env::var_os("UPDATE_SCHEMA").is_some() {
+            fs::write(&path, &schema).unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            schema,
+            "schema.json is stale, rerun the tests with UPDATE_SCHEMA=1"
+        );
+    }
+
+    #[test]
+    fn root_tags_are_unique() {
+        let mut tags: Vec<_> = roots().into_iter().map(|(tag, _)| tag).collect();
+        tags.sort_unstable();
+        tags.dedup();
+        assert_eq!(tags.len(), roots().len());
+    }
+}
@@ -1,14 +1,218 @@
 //! State transition types
 //! by hongbo
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 use enum_dispatch::enum_dispatch;
 use solana_program::{
+    hash::hashv,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
-    clock::UnixTimestamp,
+    clock::{Slot, UnixTimestamp},
 };
-use crate::fees::AuctionFees;
+use crate::{
+    curve::base::DutchPrice,
+    error::AuctionError,
+    fees::{AuctionFees, FeeSplits},
+    rules::{BidRules, RetractPolicy, RetractRules, SoftClose, WalletCaps},
+};
+use std::{
+    cmp::{Ordering, Reverse},
+    convert::TryFrom,
+};
+
+/// Maximum number of lots, and so of winning bids, in one auction
+pub const MAX_WINNERS: usize = 10;
+
+const WINNERS_LEN: usize = MAX_WINNERS * WinningBid::LEN;
+
+/// Number of timed bids a bid record keeps for a candle auction, the last one
+/// before the candle window and the ones inside it
+pub const MAX_BID_HISTORY: usize = 8;
+
+const BID_HISTORY_LEN: usize = MAX_BID_HISTORY * TimedBid::LEN;
+
+/// Borsh encoding of a DutchPrice, both prices, the curve variant and the
+/// parameters of the largest curve
+const DUTCH_PRICE_MAX_LEN: usize = 2 * 8 + 1 + 10;
+
+/// Version byte of a Borsh encoded AuctionV2, 1 is the packed V1
+pub const BORSH_AUCTION_VERSION: u8 = 3;
+
+/// Program account written with Borsh behind its tag, while accounts created
+/// with the packed layout keep being read and written in it
+pub trait VersionedAccount: Pack + IsInitialized + BorshDeserialize + BorshSerialize {
+    /// Leading byte of the Borsh encoding, unique to the account type. Packed
+    /// accounts start with their initialized flag, so it is never 0 or 1.
+    const TAG: u8;
+
+    /// Size of a new account, room for the tag and the largest Borsh encoding
+    const SPACE: usize;
+
+    /// Unpack an account in either layout, checking that it is initialized
+    fn unpack_versioned(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.split_first() {
+            Some((&tag, mut rest)) if tag == Self::TAG => {
+                let value = Self::deserialize(&mut rest)
+                    .map_err(|_| ProgramError::InvalidAccountData)?;
+                if value.is_initialized() {
+                    Ok(value)
+                } else {
+                    Err(ProgramError::UninitializedAccount)
+                }
+            }
+            _ => Self::unpack(input),
+        }
+    }
+
+    /// Pack an account in the layout its size was allocated for
+    fn pack_versioned(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if dst.len() == Self::LEN {
+            return Self::pack(src, dst);
+        }
+        let (tag, mut rest) = dst
+            .split_first_mut()
+            .ok_or(ProgramError::AccountDataTooSmall)?;
+        *tag = Self::TAG;
+        src.serialize(&mut rest)
+            .map_err(|_| ProgramError::AccountDataTooSmall)
+    }
+}
+
+/// Format of an auction
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub enum AuctionType {
+    /// Ascending open bids, the highest bids win at the end
+    #[default]
+    English,
+    /// Descending price, the first buyers to accept the current price win
+    Dutch,
+    /// Sealed bids committed as hashes, then revealed after the end
+    Sealed,
+    /// Bids for a quantity of units at a unit price, every filled unit is
+    /// paid at the clearing price
+    MultiUnit,
+    /// Ascending open bids with a close picked at random inside the candle
+    /// window after the end, only bids up to the close count
+    Candle,
+}
+
+impl TryFrom<u8> for AuctionType {
+    type Error = ProgramError;
+
+    fn try_from(auction_type: u8) -> Result<Self, Self::Error> {
+        match auction_type {
+            0 => Ok(AuctionType::English),
+            1 => Ok(AuctionType::Dutch),
+            2 => Ok(AuctionType::Sealed),
+            3 => Ok(AuctionType::MultiUnit),
+            4 => Ok(AuctionType::Candle),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Price the winners of an auction pay
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub enum PricingRule {
+    /// Every winner pays their own bid
+    #[default]
+    FirstPrice,
+    /// Vickrey rule: winners pay the highest bid outside the winning set, or
+    /// the reserve price if that is higher
+    SecondPrice,
+}
+
+impl TryFrom<u8> for PricingRule {
+    type Error = ProgramError;
+
+    fn try_from(pricing_rule: u8) -> Result<Self, Self::Error> {
+        match pricing_rule {
+            0 => Ok(PricingRule::FirstPrice),
+            1 => Ok(PricingRule::SecondPrice),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Lifecycle of an auction, the phases in between follow from the clock
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub enum AuctionStatus {
+    /// Open to bids until the end, then to settlement
+    #[default]
+    Active,
+    /// Canceled by the authority, bids can be refunded
+    Canceled,
+    /// Lot and proceeds were paid out
+    Settled,
+}
+
+impl TryFrom<u8> for AuctionStatus {
+    type Error = ProgramError;
+
+    fn try_from(status: u8) -> Result<Self, Self::Error> {
+        match status {
+            0 => Ok(AuctionStatus::Active),
+            1 => Ok(AuctionStatus::Canceled),
+            2 => Ok(AuctionStatus::Settled),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Hash committing `bidder` to an amount in `auction` that is revealed later,
+/// salted so that it cannot be found by trying every amount. The keys keep a
+/// commitment from being copied by another bidder or into another auction.
+pub fn hash_commitment(
+    auction: &Pubkey,
+    bidder: &Pubkey,
+    amount: u64,
+    salt: &[u8; 32],
+) -> [u8; 32] {
+    hashv(&[auction.as_ref(), bidder.as_ref(), &amount.to_le_bytes(), salt]).to_bytes()
+}
+
+/// Leaf of an allowlist Merkle tree, for a bidder allowed to escrow up to
+/// `cap`, or any amount when `cap` is zero
+pub fn allowlist_leaf(bidder: &Pubkey, cap: u64) -> [u8; 32] {
+    hashv(&[bidder.as_ref(), &cap.to_le_bytes()]).to_bytes()
+}
+
+/// Check a Merkle proof that the leaf of `bidder` and `cap` is in the
+/// allowlist with `root`. Pairs of nodes are hashed in sorted order, so the
+/// proof needs no left or right flags.
+pub fn verify_allowlist_proof(root: &[u8; 32], bidder: &Pubkey, cap: u64, proof: &[[u8; 32]]) -> bool {
+    let node = proof.iter().fold(allowlist_leaf(bidder, cap), |node, sibling| {
+        if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+    node == *root
+}
+
+/// Hash of the first slot at or after `slot` in the data of the SlotHashes
+/// sysvar, a vector of (slot, hash) most recent first, too large to
+/// deserialize whole. None until that slot has a hash, or once the history no
+/// longer reaches back before `slot` to tell which slot came first.
+pub fn find_slot_hash(slot_hashes: &[u8], slot: Slot) -> Option<[u8; 32]> {
+    let len = u64::from_le_bytes(*array_ref![slot_hashes.get(..8)?, 0, 8]) as usize;
+    let entries = slot_hashes.get(8..)?.chunks_exact(40).take(len);
+    let mut found = None;
+    for entry in entries {
+        let entry = array_ref![entry, 0, 40];
+        let (entry_slot, hash) = array_refs![entry, 8, 32];
+        if u64::from_le_bytes(*entry_slot) < slot {
+            return found;
+        }
+        found = Some(*hash);
+    }
+    None
+}
 
 /// Trait representing access to program state across all versions
 #[enum_dispatch]
@@ -34,13 +238,22 @@ pub trait AuctionState {
     fn end_timestamp(&self) -> UnixTimestamp; 
 
     fn canceled(&self) -> u8;
+
+    /// Lot and proceeds were paid out after the end of the auction
+    fn settled(&self) -> bool;
+
+    /// Canceled by the authority, or settled
+    fn status(&self) -> AuctionStatus;
 }
 
 /// All versions of AuctionState
+#[allow(clippy::large_enum_variant)]
 #[enum_dispatch(AuctionState)]
 pub enum AuctionVersion {
-    /// Latest version, used for all new auctions
+    /// First version, read until migrated
     AuctionV1,
+    /// Latest version, used for all new auctions
+    AuctionV2,
 }
 
 /// AuctionVersion does not implement program_pack::Pack because there are size
@@ -48,15 +261,33 @@ pub enum AuctionVersion {
 /// special implementations are provided here
 impl AuctionVersion {
     /// Size of the latest version of the AuctionState
-    pub const LATEST_LEN: usize = 1 + AuctionV1::LEN; // add one for the version enum
+    pub const LATEST_LEN: usize = 1 + AuctionV2::MAX_LEN; // add one for the version enum
 
-    /// Pack a auction into a byte array, based on its version
+    /// Pack a auction into a byte array, based on its version. V2 is
+    /// written with Borsh, the layout published in the client schema.
     pub fn pack(src: Self, dst: &mut [u8]) -> Result<(), ProgramError> {
         match src {
             Self::AuctionV1(auction_info) => {
                 dst[0] = 1;
                 AuctionV1::pack(auction_info, &mut dst[1..])
             }
+            Self::AuctionV2(auction_info) => {
+                dst[0] = BORSH_AUCTION_VERSION;
+                auction_info
+                    .serialize(&mut &mut dst[1..])
+                    .map_err(|_| ProgramError::AccountDataTooSmall)
+            }
+        }
+    }
+
+    /// Unpack a Borsh encoded AuctionV2
+    fn unpack_borsh(mut input: &[u8]) -> Result<AuctionV2, ProgramError> {
+        let auction = AuctionV2::deserialize(&mut input)
+            .map_err(|_| ProgramError::InvalidAccountData)?;
+        if auction.is_initialized {
+            Ok(auction)
+        } else {
+            Err(ProgramError::UninitializedAccount)
         }
     }
 
@@ -68,6 +299,21 @@ impl AuctionVersion {
             .ok_or(ProgramError::InvalidAccountData)?;
         match version {
             1 => Ok(Box::new(AuctionV1::unpack(rest)?)),
+            BORSH_AUCTION_VERSION => Ok(Box::new(Self::unpack_borsh(rest)?)),
+            _ => Err(ProgramError::UninitializedAccount),
+        }
+    }
+
+    /// Unpack the auction account as the latest version, for instructions
+    /// that write the auction state back. Older versions have to be migrated
+    /// first, they do not have the room for it.
+    pub fn unpack_latest(input: &[u8]) -> Result<AuctionV2, ProgramError> {
+        let (&version, rest) = input
+            .split_first()
+            .ok_or(ProgramError::InvalidAccountData)?;
+        match version {
+            1 => Err(AuctionError::OutdatedAuction.into()),
+            BORSH_AUCTION_VERSION => Self::unpack_borsh(rest),
             _ => Err(ProgramError::UninitializedAccount),
         }
     }
@@ -82,7 +328,8 @@ impl AuctionVersion {
     }
 }
 
-/// Program states.
+/// Program states of the first version, read from auctions created before
+/// V2 until they are migrated. The layout is frozen, new fields go in V2.
 #[repr(C)]
 #[derive(Debug, Default, PartialEq)]
 pub struct AuctionV1 {
@@ -154,6 +401,17 @@ impl AuctionState for AuctionV1 {
         self.canceled
     }
 
+    fn settled(&self) -> bool {
+        false
+    }
+
+    fn status(&self) -> AuctionStatus {
+        if self.canceled != 0 {
+            AuctionStatus::Canceled
+        } else {
+            AuctionStatus::Active
+        }
+    }
 }
 
 impl Sealed for AuctionV1 {}
@@ -163,11 +421,13 @@ impl IsInitialized for AuctionV1 {
     }
 }
 
+/// The nonce and canceled flag are padded to 8 bytes, the timestamps are i64
+/// little endian
 impl Pack for AuctionV1 {
-    const LEN: usize = 323;
+    const LEN: usize = 1 + 4 * 32 + AuctionFees::LEN + 8 + 8 + 8 + 8;
 
     fn pack_into_slice(&self, output: &mut [u8]) {
-        let output = array_mut_ref![output, 0, 289];
+        let output = array_mut_ref![output, 0, AuctionV1::LEN];
         let (
             is_initialized,
             token_program_id,
@@ -179,22 +439,24 @@ impl Pack for AuctionV1 {
             start_timestamp,
             end_timestamp,
             canceled,
-        ) = mut_array_refs![output, 1, 32, 32, 32, 32, 16, 8, 64, 64,8];
+        ) = mut_array_refs![output, 1, 32, 32, 32, 32, AuctionFees::LEN, 8, 8, 8, 8];
         is_initialized[0] = self.is_initialized as u8;
         token_program_id.copy_from_slice(self.token_program_id.as_ref());
         token.copy_from_slice(self.token.as_ref());
         pool.copy_from_slice(self.pool.as_ref());
         fee_account.copy_from_slice(self.fee_account.as_ref());
         self.fees.pack_into_slice(&mut fees[..]);
-        nonce[0] = self.nonce as u8;
-        start_timestamp.copy_from_slice(&self.start_timestamp.to_le_bytes());
-        end_timestamp.copy_from_slice(&self.end_timestamp.to_le_bytes());
-        canceled[0] = self.canceled as u8;
+        *nonce = [0; 8];
+        nonce[0] = self.nonce;
+        *start_timestamp = self.start_timestamp.to_le_bytes();
+        *end_timestamp = self.end_timestamp.to_le_bytes();
+        *canceled = [0; 8];
+        canceled[0] = self.canceled;
     }
 
-    /// Unpacks a byte buffer into a [SwapV1](struct.SwapV1.html).
+    /// Unpacks a byte buffer into a [AuctionV1](struct.AuctionV1.html).
     fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
-        let input = array_ref![input, 0, 289];
+        let input = array_ref![input, 0, AuctionV1::LEN];
         #[allow(clippy::ptr_offset_with_cast)]
         let (
             is_initialized,
@@ -207,7 +469,7 @@ impl Pack for AuctionV1 {
             start_timestamp,
             end_timestamp,
             canceled,
-        ) = array_refs![input, 1, 32, 32, 32, 32, 16, 8, 64,64,8];
+        ) = array_refs![input, 1, 32, 32, 32, 32, AuctionFees::LEN, 8, 8, 8, 8];
         Ok(Self {
             is_initialized: match is_initialized {
                 [0] => false,
@@ -219,10 +481,1528 @@ impl Pack for AuctionV1 {
             pool: Pubkey::new_from_array(*pool),
             fee_account: Pubkey::new_from_array(*fee_account),
             fees: AuctionFees::unpack_from_slice(fees)?,
-            nonce : nonce[0],
-            start_timestamp: start_timestamp[0] as i64,
-            end_timestamp: end_timestamp[0] as i64,
+            nonce: nonce[0],
+            start_timestamp: i64::from_le_bytes(*start_timestamp),
+            end_timestamp: i64::from_le_bytes(*end_timestamp),
             canceled: canceled[0],
         })
     }
-}
\ No newline at end of file
+}
+
+/// Program states, written with Borsh
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct AuctionV2 {
+    /// Initialized state.
+    pub is_initialized: bool,
+
+    /// Program ID of the tokens being exchanged.
+    pub token_program_id: Pubkey,
+
+    /// owner Token account
+    pub token: Pubkey,
+
+    /// Pool token account
+    pub pool: Pubkey,
+
+    /// Pool token account to receive trading and / or withdrawal fees
+    pub fee_account: Pubkey,
+
+    // All auction fee information
+    pub fees: AuctionFees,
+
+    /// owner Token account
+    pub nonce: u8,
+
+    pub start_timestamp: UnixTimestamp,
+    pub end_timestamp: UnixTimestamp,
+
+    /// Canceled by the authority, or settled
+    pub status: AuctionStatus,
+
+    /// Number of lots sold, and so the size of the winning set
+    pub max_winners: u8,
+
+    /// Current winning bids, highest first
+    pub winners: Vec<WinningBid>,
+
+    /// Mint of the auctioned lot
+    pub lot_mint: Pubkey,
+
+    /// Vault token account escrowing the lot, owned by the auction authority
+    pub lot_vault: Pubkey,
+
+    /// Amount of the lot mint escrowed for sale
+    pub lot_amount: u64,
+
+    /// Wallet allowed to cancel and settle the auction
+    pub authority: Pubkey,
+
+    /// Lowest bid that can win, zero while a hidden reserve is unrevealed
+    pub reserve_price: u64,
+
+    /// Hash of the hidden reserve price, revealed at settlement
+    pub reserve_commitment: Option<[u8; 32]>,
+
+    /// Starting price and minimum increments of bids
+    pub bid_rules: BidRules,
+
+    /// Extension of the end on late bids
+    pub soft_close: SoftClose,
+
+    /// Seconds the end was pushed out by late bids
+    pub total_extension: UnixTimestamp,
+
+    /// Format of the auction
+    pub auction_type: AuctionType,
+
+    /// Descending price of a Dutch auction
+    pub dutch_price: DutchPrice,
+
+    /// End of the reveal phase of a sealed-bid auction
+    pub reveal_end_timestamp: UnixTimestamp,
+
+    /// Lowest deposit escrowed with a sealed bid commitment
+    pub min_deposit: u64,
+
+    /// Deposits of sealed bids that were committed but not revealed yet,
+    /// forfeited to the seller at settlement
+    pub unrevealed_deposits: u64,
+
+    /// Price the winners pay
+    pub pricing_rule: PricingRule,
+
+    /// Highest revealed bid outside the winning set
+    pub runner_up_bid: u64,
+
+    /// Number of bids placed in a multi-unit auction
+    pub bid_count: u64,
+
+    /// Number of bids processed by the clearing crank
+    pub processed_count: u64,
+
+    /// Units filled by the bids processed so far
+    pub units_allocated: u64,
+
+    /// Unit price of the lowest filled bid, paid for every filled unit
+    pub clearing_price: u64,
+
+    /// Next bid record to clear, none once every bid was cleared. PlaceUnitBid
+    /// links the records in clearing order, see [BidRecord::next_bid].
+    pub clearing_head: Option<Pubkey>,
+
+    /// Bid that ends and settles the auction at once, zero when disabled
+    pub buy_now_price: u64,
+
+    /// Seconds before the end in which a candle auction may close
+    pub candle_window: UnixTimestamp,
+
+    /// Close picked inside the candle window, zero until resolved
+    pub candle_close_timestamp: UnixTimestamp,
+
+    /// Slot whose hash draws the candle close, fixed by the first
+    /// ResolveCandle after the end, zero before
+    pub candle_close_slot: Slot,
+
+    /// Bids are paid in SOL, wrapped in a pool of the native mint
+    pub native: bool,
+
+    /// Retraction of bids before the end
+    pub retract_rules: RetractRules,
+
+    /// Bids pushed out of the winning set and not refunded yet, highest
+    /// first. Kept only when bids can be retracted, the lowest are dropped
+    /// past `MAX_WINNERS`.
+    pub standby: Vec<WinningBid>,
+
+    /// Root of the Merkle tree of bidders allowed to bid, see
+    /// [allowlist_leaf]. Anyone can bid when unset.
+    pub allowlist_root: Option<[u8; 32]>,
+
+    /// Limits on what a single wallet can bid and win
+    pub wallet_caps: WalletCaps,
+
+    /// Royalty and marketplace shares of the proceeds, paid with the
+    /// auction fee at settlement
+    pub fee_splits: FeeSplits,
+
+    /// Bids placed, sealed commitments and unit bids included, since the
+    /// auction was created or migrated to V2
+    pub bids_placed: u64,
+
+    /// Time of the latest bid, zero before the first
+    pub last_bid_timestamp: UnixTimestamp,
+
+    /// Index of the auction among those of the seller for the lot mint, see
+    /// [find_auction_address]
+    pub auction_index: u64,
+
+    /// Bump seed of the auction program address, zero for auctions created
+    /// at a keypair address before
+    pub bump_seed: u8,
+}
+
+impl AuctionV2 {
+    /// Largest Borsh encoding, with both bid lists full and every option set
+    pub const MAX_LEN: usize = 1
+        + 4 * 32
+        + AuctionFees::LEN
+        + 1
+        + 2 * 8
+        + 2
+        + 4
+        + WINNERS_LEN
+        + 2 * 32
+        + 8
+        + 32
+        + 8
+        + 33
+        + BidRules::LEN
+        + SoftClose::LEN
+        + 8
+        + 1
+        + DUTCH_PRICE_MAX_LEN
+        + 3 * 8
+        + 1
+        + 5 * 8
+        + 33
+        + 4 * 8
+        + 1
+        + RetractRules::LEN
+        + 4
+        + WINNERS_LEN
+        + 33
+        + WalletCaps::LEN
+        + FeeSplits::LEN
+        + 3 * 8
+        + 1;
+
+    /// Is the auction over at `now`: past its end, a Dutch auction with
+    /// every lot bought, or a sealed-bid auction past its reveal phase
+    pub fn is_over(&self, now: UnixTimestamp) -> bool {
+        match self.auction_type {
+            AuctionType::English | AuctionType::Candle => now > self.end_timestamp,
+            AuctionType::Dutch => {
+                now > self.end_timestamp || self.winners.len() >= self.max_winners as usize
+            }
+            AuctionType::Sealed => now > self.reveal_end_timestamp,
+            AuctionType::MultiUnit => now > self.end_timestamp,
+        }
+    }
+
+    /// Bid a new total from `bidder` has to beat to enter the winning set:
+    /// the lowest winner of a full set, unless the bidder is already in it
+    pub fn bid_to_beat(&self, bidder: &Pubkey) -> Option<u64> {
+        if self.winners.len() < self.max_winners as usize
+            || self.winners.iter().any(|w| w.bidder == *bidder)
+        {
+            None
+        } else {
+            self.winners.last().map(|w| w.amount)
+        }
+    }
+
+    /// Put a bidder's total escrowed amount into the winning set, keeping it
+    /// sorted highest first, and return the winning bid it pushed out of a
+    /// full set. Equal bids keep their earlier position, so a bid that only
+    /// matches the lowest winner of a full set is rejected.
+    pub fn place_winning_bid(
+        &mut self,
+        bidder: &Pubkey,
+        amount: u64,
+    ) -> Result<Option<WinningBid>, AuctionError> {
+        let mut displaced = None;
+        if let Some(index) = self.winners.iter().position(|w| w.bidder == *bidder) {
+            self.winners.remove(index);
+        } else if self.winners.len() >= self.max_winners as usize {
+            match self.winners.last() {
+                Some(lowest) if amount > lowest.amount => {
+                    displaced = self.winners.pop();
+                }
+                _ => return Err(AuctionError::BidTooLow),
+            }
+        }
+        let index = self
+            .winners
+            .iter()
+            .position(|w| w.amount < amount)
+            .unwrap_or(self.winners.len());
+        self.winners.insert(
+            index,
+            WinningBid {
+                bidder: *bidder,
+                amount,
+            },
+        );
+        Ok(displaced)
+    }
+
+    /// Put a bid of an auction that allows retraction into the winning set,
+    /// keeping the bid it pushed out on standby
+    pub fn place_retractable_bid(&mut self, bidder: &Pubkey, amount: u64) -> Result<(), AuctionError> {
+        if self.retract_rules.policy == RetractPolicy::Forbidden {
+            return self.place_winning_bid(bidder, amount).map(|_| ());
+        }
+        self.standby.retain(|w| w.bidder != *bidder);
+        if let Some(displaced) = self.place_winning_bid(bidder, amount)? {
+            // standby bids never beat a winner, the displaced one was the
+            // lowest winner
+            self.standby.insert(0, displaced);
+            self.standby.truncate(MAX_WINNERS);
+        }
+        Ok(())
+    }
+
+    /// Take the bid of `bidder` out of the winning set or off standby, and
+    /// return whether it was winning. The highest standby bid takes the place
+    /// of a retracted winning bid.
+    pub fn retract_bid(&mut self, bidder: &Pubkey) -> bool {
+        match self.winners.iter().position(|w| w.bidder == *bidder) {
+            Some(index) => {
+                self.winners.remove(index);
+                if !self.standby.is_empty() {
+                    self.winners.push(self.standby.remove(0));
+                }
+                true
+            }
+            None => {
+                self.standby.retain(|w| w.bidder != *bidder);
+                false
+            }
+        }
+    }
+
+    /// Units of the lot each winner of a single-price auction receives
+    pub fn lot_per_winner(&self) -> u64 {
+        self.lot_amount / u64::from(self.max_winners.max(1))
+    }
+
+    /// Start of the candle window
+    pub fn candle_window_start(&self) -> UnixTimestamp {
+        self.end_timestamp.saturating_sub(self.candle_window)
+    }
+
+    /// Slot whose hash will draw the candle close when resolution starts at
+    /// `slot`, the next one, so that its hash is not known yet
+    pub fn candle_close_slot_after(slot: Slot) -> Slot {
+        slot.saturating_add(1)
+    }
+
+    /// Close of a candle auction drawn from `seed`, uniformly inside the
+    /// candle window
+    pub fn draw_candle_close(&self, seed: &[u8; 32]) -> UnixTimestamp {
+        let draw = u64::from_le_bytes(*array_ref![seed, 0, 8]);
+        let offset = draw % (self.candle_window as u64 + 1);
+        self.candle_window_start() + offset as UnixTimestamp
+    }
+
+    /// Process the bid at `address`, which must be the clearing head, and
+    /// move the head to the bid linked after it. Bids are linked in order of
+    /// unit price highest first, then of bid time, then of bidder. They fill
+    /// while units remain, the last one partially, and the unit price of the
+    /// last filled bid becomes the clearing price.
+    pub fn clear_bid(
+        &mut self,
+        address: &Pubkey,
+        bid_record: &mut BidRecord,
+    ) -> Result<(), AuctionError> {
+        if bid_record.cleared || bid_record.quantity == 0 {
+            return Err(AuctionError::InvalidBidRecord);
+        }
+        if self.clearing_head != Some(*address) {
+            return Err(AuctionError::InvalidClearingOrder);
+        }
+        let remaining = self
+            .lot_amount
+            .checked_sub(self.units_allocated)
+            .ok_or(AuctionError::CalculationFailure)?;
+        let filled = if bid_record.unit_price < self.reserve_price {
+            0
+        } else {
+            bid_record.quantity.min(remaining)
+        };
+        if filled > 0 {
+            self.units_allocated += filled;
+            self.clearing_price = bid_record.unit_price;
+        }
+        bid_record.filled = filled;
+        bid_record.cleared = true;
+        self.processed_count = self
+            .processed_count
+            .checked_add(1)
+            .ok_or(AuctionError::CalculationFailure)?;
+        self.clearing_head = bid_record.next_bid;
+        Ok(())
+    }
+
+    /// Price every winner pays under the second-price rule: the highest
+    /// revealed bid outside the winning set, or the reserve if that is higher
+    pub fn second_price(&self) -> u64 {
+        self.runner_up_bid.max(self.reserve_price)
+    }
+
+    /// Count a bid placed at `timestamp`
+    pub fn track_bid(&mut self, timestamp: UnixTimestamp) {
+        self.bids_placed = self.bids_placed.saturating_add(1);
+        self.last_bid_timestamp = timestamp;
+    }
+}
+
+impl AuctionState for AuctionV2 {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    fn token_program_id(&self) -> &Pubkey {
+        &self.token_program_id
+    }
+
+    fn token_account(&self) -> &Pubkey {
+        &self.token
+    }
+
+    fn pool(&self) -> &Pubkey {
+        &self.pool
+    }
+
+    fn fee_account(&self) -> &Pubkey {
+        &self.fee_account
+    }
+
+    fn fees(&self) -> &AuctionFees {
+        &self.fees
+    }
+
+    fn nonce(&self) -> u8 {
+        self.nonce
+    }
+
+    fn start_timestamp(&self) -> UnixTimestamp {
+        self.start_timestamp
+    }
+
+    fn end_timestamp(&self) -> UnixTimestamp {
+        self.end_timestamp
+    }
+
+    fn canceled(&self) -> u8 {
+        (self.status == AuctionStatus::Canceled) as u8
+    }
+
+    fn settled(&self) -> bool {
+        self.status == AuctionStatus::Settled
+    }
+
+    fn status(&self) -> AuctionStatus {
+        self.status
+    }
+}
+
+/// Accessors of the fields V1 does not have
+impl AuctionV2 {
+    /// Number of lots sold, and so the size of the winning set
+    pub fn max_winners(&self) -> u8 {
+        self.max_winners
+    }
+
+    /// Current winning bids, highest first
+    pub fn winners(&self) -> &[WinningBid] {
+        &self.winners
+    }
+
+    /// Current leading bid, if any
+    pub fn highest_bid(&self) -> Option<&WinningBid> {
+        self.winners.first()
+    }
+
+    /// Mint of the auctioned lot
+    pub fn lot_mint(&self) -> &Pubkey {
+        &self.lot_mint
+    }
+
+    /// Address of the vault token account escrowing the lot
+    pub fn lot_vault(&self) -> &Pubkey {
+        &self.lot_vault
+    }
+
+    /// Amount of the lot mint escrowed for sale
+    pub fn lot_amount(&self) -> u64 {
+        self.lot_amount
+    }
+
+    /// Wallet allowed to cancel and settle the auction
+    pub fn authority(&self) -> &Pubkey {
+        &self.authority
+    }
+
+    /// Lowest bid that can win, zero while a hidden reserve is unrevealed
+    pub fn reserve_price(&self) -> u64 {
+        self.reserve_price
+    }
+
+    /// Hash of the hidden reserve price, if any
+    pub fn reserve_commitment(&self) -> Option<&[u8; 32]> {
+        self.reserve_commitment.as_ref()
+    }
+
+    /// Starting price and minimum increments of bids
+    pub fn bid_rules(&self) -> &BidRules {
+        &self.bid_rules
+    }
+
+    /// Extension of the end on late bids
+    pub fn soft_close(&self) -> &SoftClose {
+        &self.soft_close
+    }
+
+    /// Seconds the end was pushed out by late bids
+    pub fn total_extension(&self) -> UnixTimestamp {
+        self.total_extension
+    }
+
+    /// Format of the auction
+    pub fn auction_type(&self) -> AuctionType {
+        self.auction_type
+    }
+
+    /// Descending price of a Dutch auction
+    pub fn dutch_price(&self) -> &DutchPrice {
+        &self.dutch_price
+    }
+
+    /// End of the reveal phase of a sealed-bid auction, which starts at the
+    /// end timestamp
+    pub fn reveal_end_timestamp(&self) -> UnixTimestamp {
+        self.reveal_end_timestamp
+    }
+
+    /// Lowest deposit escrowed with a sealed bid commitment
+    pub fn min_deposit(&self) -> u64 {
+        self.min_deposit
+    }
+
+    /// Deposits of sealed bids that were committed but not revealed yet
+    pub fn unrevealed_deposits(&self) -> u64 {
+        self.unrevealed_deposits
+    }
+
+    /// Price the winners pay
+    pub fn pricing_rule(&self) -> PricingRule {
+        self.pricing_rule
+    }
+
+    /// Highest revealed bid outside the winning set, with the leading bid
+    /// the top two revealed bids of a single-lot auction
+    pub fn runner_up_bid(&self) -> u64 {
+        self.runner_up_bid
+    }
+
+    /// Number of bids placed in a multi-unit auction
+    pub fn bid_count(&self) -> u64 {
+        self.bid_count
+    }
+
+    /// Number of bids processed by the clearing crank
+    pub fn processed_count(&self) -> u64 {
+        self.processed_count
+    }
+
+    /// Units filled by the bids processed so far
+    pub fn units_allocated(&self) -> u64 {
+        self.units_allocated
+    }
+
+    /// Unit price of the lowest filled bid, paid for every filled unit
+    pub fn clearing_price(&self) -> u64 {
+        self.clearing_price
+    }
+
+    /// Bid that ends and settles the auction at once, zero when disabled
+    pub fn buy_now_price(&self) -> u64 {
+        self.buy_now_price
+    }
+
+    /// Seconds before the end in which a candle auction may close
+    pub fn candle_window(&self) -> UnixTimestamp {
+        self.candle_window
+    }
+
+    /// Close picked inside the candle window, zero until resolved
+    pub fn candle_close_timestamp(&self) -> UnixTimestamp {
+        self.candle_close_timestamp
+    }
+
+    /// Bids are paid in SOL, wrapped in a pool of the native mint
+    pub fn native(&self) -> bool {
+        self.native
+    }
+
+    /// Retraction of bids before the end
+    pub fn retract_rules(&self) -> &RetractRules {
+        &self.retract_rules
+    }
+
+    /// Bids pushed out of the winning set and not refunded yet, highest
+    /// first, that move up when a winning bid is retracted
+    pub fn standby(&self) -> &[WinningBid] {
+        &self.standby
+    }
+
+    /// Root of the Merkle tree of bidders allowed to bid, if gated
+    pub fn allowlist_root(&self) -> Option<&[u8; 32]> {
+        self.allowlist_root.as_ref()
+    }
+
+    /// Limits on what a single wallet can bid and win
+    pub fn wallet_caps(&self) -> &WalletCaps {
+        &self.wallet_caps
+    }
+
+    /// Royalty and marketplace shares of the proceeds
+    pub fn fee_splits(&self) -> &FeeSplits {
+        &self.fee_splits
+    }
+
+    /// Bids placed, counted from V2 on
+    pub fn bids_placed(&self) -> u64 {
+        self.bids_placed
+    }
+
+    /// Time of the latest bid, zero before the first or before V2
+    pub fn last_bid_timestamp(&self) -> UnixTimestamp {
+        self.last_bid_timestamp
+    }
+}
+
+impl IsInitialized for AuctionV2 {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+/// A V1 auction has no lot, bids or authority of its own. The authority is
+/// filled in by MigrateAuction, the rest of what V1 lacks starts as a
+/// single-lot auction with default rules.
+impl From<AuctionV1> for AuctionV2 {
+    fn from(auction: AuctionV1) -> Self {
+        let status = auction.status();
+        Self {
+            is_initialized: auction.is_initialized,
+            token_program_id: auction.token_program_id,
+            token: auction.token,
+            pool: auction.pool,
+            fee_account: auction.fee_account,
+            fees: auction.fees,
+            nonce: auction.nonce,
+            start_timestamp: auction.start_timestamp,
+            end_timestamp: auction.end_timestamp,
+            status,
+            max_winners: 1,
+            ..Self::default()
+        }
+    }
+}
+
+fn pack_hash_option(src: &Option<[u8; 32]>, dst: &mut [u8; 33]) {
+    let (tag, body) = mut_array_refs![dst, 1, 32];
+    match src {
+        Some(hash) => {
+            tag[0] = 1;
+            body.copy_from_slice(hash);
+        }
+        None => {
+            tag[0] = 0;
+            *body = [0; 32];
+        }
+    }
+}
+
+fn unpack_hash_option(src: &[u8; 33]) -> Result<Option<[u8; 32]>, ProgramError> {
+    let (tag, body) = array_refs![src, 1, 32];
+    match *tag {
+        [0] => Ok(None),
+        [1] => Ok(Some(*body)),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// A bid in the winning set of an auction
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct WinningBid {
+    /// Wallet that placed the bid
+    pub bidder: Pubkey,
+
+    /// Total amount escrowed by the bidder
+    pub amount: u64,
+}
+
+impl Sealed for WinningBid {}
+impl IsInitialized for WinningBid {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Pack for WinningBid {
+    const LEN: usize = 40;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 40];
+        let (bidder, amount) = mut_array_refs![output, 32, 8];
+        bidder.copy_from_slice(self.bidder.as_ref());
+        *amount = self.amount.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 40];
+        let (bidder, amount) = array_refs![input, 32, 8];
+        Ok(Self {
+            bidder: Pubkey::new_from_array(*bidder),
+            amount: u64::from_le_bytes(*amount),
+        })
+    }
+}
+
+/// Total bid of a bidder from a point in time
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct TimedBid {
+    /// Time of the bid
+    pub timestamp: UnixTimestamp,
+    /// Total escrowed by the bidder after the bid
+    pub amount: u64,
+}
+
+impl Sealed for TimedBid {}
+impl IsInitialized for TimedBid {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Pack for TimedBid {
+    const LEN: usize = 16;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 16];
+        let (timestamp, amount) = mut_array_refs![output, 8, 8];
+        *timestamp = self.timestamp.to_le_bytes();
+        *amount = self.amount.to_le_bytes();
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 16];
+        let (timestamp, amount) = array_refs![input, 8, 8];
+        Ok(Self {
+            timestamp: i64::from_le_bytes(*timestamp),
+            amount: u64::from_le_bytes(*amount),
+        })
+    }
+}
+
+/// Order of a bid in the clearing of a multi-unit auction: highest unit
+/// price first, then earliest bid, then bidder address
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct ClearingKey {
+    /// Unit price, higher prices come first
+    pub unit_price: u64,
+    /// Time of the bid
+    pub timestamp: UnixTimestamp,
+    /// Wallet that placed the bid
+    pub bidder: Pubkey,
+}
+
+impl Ord for ClearingKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (Reverse(self.unit_price), self.timestamp, self.bidder).cmp(&(
+            Reverse(other.unit_price),
+            other.timestamp,
+            other.bidder,
+        ))
+    }
+}
+
+impl PartialOrd for ClearingKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Sealed for ClearingKey {}
+impl IsInitialized for ClearingKey {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+}
+
+impl Pack for ClearingKey {
+    const LEN: usize = 48;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 48];
+        let (unit_price, timestamp, bidder) = mut_array_refs![output, 8, 8, 32];
+        *unit_price = self.unit_price.to_le_bytes();
+        *timestamp = self.timestamp.to_le_bytes();
+        bidder.copy_from_slice(self.bidder.as_ref());
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 48];
+        let (unit_price, timestamp, bidder) = array_refs![input, 8, 8, 32];
+        Ok(Self {
+            unit_price: u64::from_le_bytes(*unit_price),
+            timestamp: i64::from_le_bytes(*timestamp),
+            bidder: Pubkey::new_from_array(*bidder),
+        })
+    }
+}
+
+/// Seed prefix for the program address of an auction
+pub const AUCTION_SEED: &[u8] = b"auction";
+
+/// Find the program address of the auction number `auction_index` of a
+/// seller for a lot mint
+pub fn find_auction_address(
+    program_id: &Pubkey,
+    seller: &Pubkey,
+    lot_mint: &Pubkey,
+    auction_index: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            AUCTION_SEED,
+            &seller.to_bytes(),
+            &lot_mint.to_bytes(),
+            &auction_index.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Find the program authority of an auction, owner of its pool and lot
+/// vault, and the nonce stored in the auction
+pub fn find_authority_address(program_id: &Pubkey, auction: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&auction.to_bytes()], program_id)
+}
+
+/// Seed prefix for the program address of a bid record
+pub const BID_RECORD_SEED: &[u8] = b"bid_record";
+
+/// Find the program address of the bid record for a bidder in an auction
+pub fn find_bid_record_address(
+    program_id: &Pubkey,
+    auction: &Pubkey,
+    bidder: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[BID_RECORD_SEED, &auction.to_bytes(), &bidder.to_bytes()],
+        program_id,
+    )
+}
+
+/// Seed prefix for the program address of the temporary account unwrapping
+/// SOL out of the pool of a native SOL auction
+pub const UNWRAP_SEED: &[u8] = b"unwrap";
+
+/// Find the program address of the temporary unwrap account of an auction
+pub fn find_unwrap_address(program_id: &Pubkey, auction: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[UNWRAP_SEED, &auction.to_bytes()], program_id)
+}
+
+/// Seed of the program address of the program config
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Find the program address of the program config
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+/// Seed prefix of the program address holding an auction while it is
+/// migrated to the latest version
+pub const MIGRATION_SEED: &[u8] = b"migration";
+
+/// Find the program address holding `auction` while it is migrated
+pub fn find_migration_address(program_id: &Pubkey, auction: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MIGRATION_SEED, &auction.to_bytes()], program_id)
+}
+
+/// Program-wide constraints on new auctions, set by the protocol owner
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct ProgramConfig {
+    /// Initialized state.
+    pub is_initialized: bool,
+
+    /// Bump seed of the config program address
+    pub bump_seed: u8,
+
+    /// Protocol owner, the only signer allowed to update the config
+    pub owner: Pubkey,
+
+    /// Lowest auction fee a new auction may charge
+    pub min_fees: AuctionFees,
+
+    /// Highest auction fee a new auction may charge
+    pub max_fees: AuctionFees,
+
+    /// Owner the fee account of a new auction must have, any when unset
+    pub fee_owner: Option<Pubkey>,
+
+    /// New auctions cannot be created while paused
+    pub paused: bool,
+}
+
+impl ProgramConfig {
+    /// Validate that the fee bounds are fees and not crossed
+    pub fn validate(&self) -> Result<(), AuctionError> {
+        self.min_fees.validate(None)?;
+        self.max_fees.validate(None)?;
+        if self.min_fees.cmp_fraction(&self.max_fees) == Ordering::Greater {
+            return Err(AuctionError::InvalidFee);
+        }
+        Ok(())
+    }
+}
+
+impl VersionedAccount for ProgramConfig {
+    const TAG: u8 = 4;
+    const SPACE: usize = 1 + ProgramConfig::LEN;
+}
+
+impl Sealed for ProgramConfig {}
+impl IsInitialized for ProgramConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for ProgramConfig {
+    const LEN: usize = 100;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 100];
+        let (is_initialized, bump_seed, owner, min_fees, max_fees, fee_owner, paused) =
+            mut_array_refs![output, 1, 1, 32, AuctionFees::LEN, AuctionFees::LEN, 33, 1];
+        is_initialized[0] = self.is_initialized as u8;
+        bump_seed[0] = self.bump_seed;
+        owner.copy_from_slice(self.owner.as_ref());
+        self.min_fees.pack_into_slice(&mut min_fees[..]);
+        self.max_fees.pack_into_slice(&mut max_fees[..]);
+        pack_hash_option(&self.fee_owner.map(|key| key.to_bytes()), fee_owner);
+        paused[0] = self.paused as u8;
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 100];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (is_initialized, bump_seed, owner, min_fees, max_fees, fee_owner, paused) =
+            array_refs![input, 1, 1, 32, AuctionFees::LEN, AuctionFees::LEN, 33, 1];
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            bump_seed: bump_seed[0],
+            owner: Pubkey::new_from_array(*owner),
+            min_fees: AuctionFees::unpack_from_slice(min_fees)?,
+            max_fees: AuctionFees::unpack_from_slice(max_fees)?,
+            fee_owner: unpack_hash_option(fee_owner)?.map(Pubkey::new_from_array),
+            paused: match paused {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+}
+
+/// Per-bidder record of the tokens escrowed in an auction pool
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSchema, BorshSerialize)]
+pub struct BidRecord {
+    /// Initialized state.
+    pub is_initialized: bool,
+
+    /// Auction the bid was placed in
+    pub auction: Pubkey,
+
+    /// Wallet that placed the bid
+    pub bidder: Pubkey,
+
+    /// Total amount escrowed in the auction pool by the bidder
+    pub amount: u64,
+
+    /// Time of the latest bid
+    pub last_bid_timestamp: UnixTimestamp,
+
+    /// Escrowed amount was refunded or claimed
+    pub claimed: bool,
+
+    /// Hash of the auction, bidder, sealed bid and a salt, see [hash_commitment]
+    pub commitment: Option<[u8; 32]>,
+
+    /// The sealed bid was revealed and topped up
+    pub revealed: bool,
+
+    /// Units bid for in a multi-unit auction
+    pub quantity: u64,
+
+    /// Price bid per unit in a multi-unit auction
+    pub unit_price: u64,
+
+    /// Units won once the bid was cleared
+    pub filled: u64,
+
+    /// The bid was processed by the clearing crank
+    pub cleared: bool,
+
+    /// Timed totals of a candle auction bidder, oldest first
+    pub bid_history: Vec<TimedBid>,
+
+    /// Most the bidder may escrow in a gated auction, zero for no cap
+    pub allowlist_cap: u64,
+
+    /// Bid record cleared after this one in a multi-unit auction, none for
+    /// the last. Not kept in the packed layout, whose records predate it.
+    pub next_bid: Option<Pubkey>,
+}
+
+impl BidRecord {
+    /// Record the current total at `timestamp`. Only the last total before
+    /// the candle window can still count, so earlier ones are dropped.
+    pub fn push_timed_bid(
+        &mut self,
+        timestamp: UnixTimestamp,
+        window_start: UnixTimestamp,
+    ) -> Result<(), AuctionError> {
+        if timestamp < window_start {
+            self.bid_history.clear();
+        }
+        if self.bid_history.len() >= MAX_BID_HISTORY {
+            return Err(AuctionError::TooManyBids);
+        }
+        self.bid_history.push(TimedBid {
+            timestamp,
+            amount: self.amount,
+        });
+        Ok(())
+    }
+
+    /// Total of the bidder at the close of a candle auction, bids at the
+    /// close itself included
+    pub fn amount_at(&self, close: UnixTimestamp) -> Option<u64> {
+        self.bid_history
+            .iter()
+            .rev()
+            .find(|bid| bid.timestamp <= close)
+            .map(|bid| bid.amount)
+    }
+
+    /// Order of the bid in the clearing of a multi-unit auction
+    pub fn clearing_key(&self) -> ClearingKey {
+        ClearingKey {
+            unit_price: self.unit_price,
+            timestamp: self.last_bid_timestamp,
+            bidder: self.bidder,
+        }
+    }
+}
+
+/// The Borsh history length is a u32 where the packed one is a u8, and the
+/// clearing link only exists in Borsh
+impl VersionedAccount for BidRecord {
+    const TAG: u8 = 2;
+    const SPACE: usize = 1 + BidRecord::LEN + 3 + 33;
+}
+
+impl Sealed for BidRecord {}
+impl IsInitialized for BidRecord {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for BidRecord {
+    const LEN: usize = 278;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, 278];
+        let (
+            is_initialized,
+            auction,
+            bidder,
+            amount,
+            last_bid_timestamp,
+            claimed,
+            commitment,
+            revealed,
+            quantity,
+            unit_price,
+            filled,
+            cleared,
+            bid_history_len,
+            bid_history,
+            allowlist_cap,
+        ) = mut_array_refs![output, 1, 32, 32, 8, 8, 1, 33, 1, 8, 8, 8, 1, 1, BID_HISTORY_LEN, 8];
+        is_initialized[0] = self.is_initialized as u8;
+        auction.copy_from_slice(self.auction.as_ref());
+        bidder.copy_from_slice(self.bidder.as_ref());
+        *amount = self.amount.to_le_bytes();
+        *last_bid_timestamp = self.last_bid_timestamp.to_le_bytes();
+        claimed[0] = self.claimed as u8;
+        pack_hash_option(&self.commitment, commitment);
+        revealed[0] = self.revealed as u8;
+        *quantity = self.quantity.to_le_bytes();
+        *unit_price = self.unit_price.to_le_bytes();
+        *filled = self.filled.to_le_bytes();
+        cleared[0] = self.cleared as u8;
+        bid_history_len[0] = self.bid_history.len() as u8;
+        for (slot, bid) in bid_history
+            .chunks_exact_mut(TimedBid::LEN)
+            .zip(self.bid_history.iter())
+        {
+            bid.pack_into_slice(slot);
+        }
+        *allowlist_cap = self.allowlist_cap.to_le_bytes();
+    }
+
+    /// Unpacks a byte buffer into a [BidRecord](struct.BidRecord.html).
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, 278];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            is_initialized,
+            auction,
+            bidder,
+            amount,
+            last_bid_timestamp,
+            claimed,
+            commitment,
+            revealed,
+            quantity,
+            unit_price,
+            filled,
+            cleared,
+            bid_history_len,
+            bid_history,
+            allowlist_cap,
+        ) = array_refs![input, 1, 32, 32, 8, 8, 1, 33, 1, 8, 8, 8, 1, 1, BID_HISTORY_LEN, 8];
+        let bid_history_len = bid_history_len[0] as usize;
+        if bid_history_len > MAX_BID_HISTORY {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self {
+            is_initialized: match is_initialized {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            auction: Pubkey::new_from_array(*auction),
+            bidder: Pubkey::new_from_array(*bidder),
+            amount: u64::from_le_bytes(*amount),
+            last_bid_timestamp: i64::from_le_bytes(*last_bid_timestamp),
+            claimed: match claimed {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            commitment: unpack_hash_option(commitment)?,
+            revealed: match revealed {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            quantity: u64::from_le_bytes(*quantity),
+            unit_price: u64::from_le_bytes(*unit_price),
+            filled: u64::from_le_bytes(*filled),
+            cleared: match cleared {
+                [0] => false,
+                [1] => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+            bid_history: bid_history
+                .chunks_exact(TimedBid::LEN)
+                .take(bid_history_len)
+                .map(TimedBid::unpack_from_slice)
+                .collect::<Result<_, _>>()?,
+            allowlist_cap: u64::from_le_bytes(*allowlist_cap),
+            next_bid: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        curve::exponential::ExponentialCurve,
+        strategies::{auction_v1s, auction_v2s},
+    };
+    use proptest::prelude::*;
+
+    #[test]
+    fn winning_set_stays_sorted_and_bounded() {
+        let mut auction = AuctionV2 {
+            max_winners: 2,
+            ..AuctionV2::default()
+        };
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        auction.place_winning_bid(&a, 100).unwrap();
+        auction.place_winning_bid(&b, 200).unwrap();
+        assert_eq!(auction.highest_bid().unwrap().bidder, b);
+
+        // matching the lowest winner of a full set is not enough
+        assert_eq!(
+            auction.place_winning_bid(&c, 100),
+            Err(AuctionError::BidTooLow)
+        );
+        auction.place_winning_bid(&c, 150).unwrap();
+        assert_eq!(
+            auction.winners(),
+            &[
+                WinningBid { bidder: b, amount: 200 },
+                WinningBid { bidder: c, amount: 150 },
+            ]
+        );
+
+        // an existing winner raising their bid moves up without growing the set
+        auction.place_winning_bid(&c, 250).unwrap();
+        assert_eq!(auction.winners().len(), 2);
+        assert_eq!(auction.highest_bid().unwrap().bidder, c);
+    }
+
+    #[test]
+    fn retracted_leader_falls_back_to_the_next_bid() {
+        let mut auction = AuctionV2 {
+            max_winners: 1,
+            retract_rules: RetractRules {
+                policy: RetractPolicy::NonLeading,
+                ..RetractRules::default()
+            },
+            ..AuctionV2::default()
+        };
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        auction.place_retractable_bid(&a, 100).unwrap();
+        auction.place_retractable_bid(&b, 200).unwrap();
+        auction.place_retractable_bid(&c, 300).unwrap();
+        assert_eq!(
+            auction.standby(),
+            &[
+                WinningBid { bidder: b, amount: 200 },
+                WinningBid { bidder: a, amount: 100 },
+            ]
+        );
+
+        // a refunded or retracted standby bid no longer falls back
+        assert!(!auction.retract_bid(&b));
+        assert!(auction.retract_bid(&c));
+        assert_eq!(auction.highest_bid(), Some(&WinningBid { bidder: a, amount: 100 }));
+        assert!(auction.standby().is_empty());
+
+        // a standby bidder raising their bid leaves the standby
+        auction.place_retractable_bid(&b, 150).unwrap();
+        auction.place_retractable_bid(&a, 175).unwrap();
+        assert_eq!(auction.standby(), &[WinningBid { bidder: b, amount: 150 }]);
+    }
+
+    #[test]
+    fn allowlist_proofs_check_bidder_and_cap() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let hash_pair = |x: [u8; 32], y: [u8; 32]| {
+            if x <= y {
+                hashv(&[&x, &y]).to_bytes()
+            } else {
+                hashv(&[&y, &x]).to_bytes()
+            }
+        };
+        let (leaf_a, leaf_b, leaf_c) = (
+            allowlist_leaf(&a, 0),
+            allowlist_leaf(&b, 500),
+            allowlist_leaf(&c, 0),
+        );
+        let root = hash_pair(hash_pair(leaf_a, leaf_b), leaf_c);
+
+        assert!(verify_allowlist_proof(&root, &a, 0, &[leaf_b, leaf_c]));
+        assert!(verify_allowlist_proof(&root, &b, 500, &[leaf_a, leaf_c]));
+        assert!(verify_allowlist_proof(&root, &c, 0, &[hash_pair(leaf_a, leaf_b)]));
+        // the cap is part of the leaf
+        assert!(!verify_allowlist_proof(&root, &b, 0, &[leaf_a, leaf_c]));
+        assert!(!verify_allowlist_proof(&root, &Pubkey::new_unique(), 0, &[leaf_b, leaf_c]));
+    }
+
+    #[test]
+    fn second_price_is_the_best_bid_left_out() {
+        let mut auction = AuctionV2 {
+            max_winners: 1,
+            reserve_price: 50,
+            pricing_rule: PricingRule::SecondPrice,
+            ..AuctionV2::default()
+        };
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert_eq!(auction.place_winning_bid(&a, 40), Ok(None));
+        assert_eq!(
+            auction.place_winning_bid(&b, 300),
+            Ok(Some(WinningBid { bidder: a, amount: 40 }))
+        );
+        // the reserve is higher than the runner-up
+        auction.runner_up_bid = 40;
+        assert_eq!(auction.second_price(), 50);
+        auction.runner_up_bid = 120;
+        assert_eq!(auction.second_price(), 120);
+    }
+
+    #[test]
+    fn bids_clear_in_order_with_a_partial_fill_at_the_margin() {
+        let mut auction = AuctionV2 {
+            auction_type: AuctionType::MultiUnit,
+            lot_amount: 100,
+            reserve_price: 5,
+            ..AuctionV2::default()
+        };
+        let bid = |unit_price, quantity, timestamp| BidRecord {
+            is_initialized: true,
+            bidder: Pubkey::new_unique(),
+            quantity,
+            unit_price,
+            last_bid_timestamp: timestamp,
+            ..BidRecord::default()
+        };
+        let (mut a, mut b, mut c, mut d) = (bid(20, 60, 3), bid(10, 30, 1), bid(10, 30, 2), bid(4, 10, 0));
+        assert!(a.clearing_key() < b.clearing_key());
+        assert!(b.clearing_key() < c.clearing_key());
+        assert!(c.clearing_key() < d.clearing_key());
+        let (ka, kb, kc, kd) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        auction.clearing_head = Some(ka);
+        a.next_bid = Some(kb);
+        b.next_bid = Some(kc);
+        c.next_bid = Some(kd);
+
+        auction.clear_bid(&ka, &mut a).unwrap();
+        // only the record linked after the last cleared one is next
+        assert_eq!(
+            auction.clear_bid(&kc, &mut c),
+            Err(AuctionError::InvalidClearingOrder)
+        );
+        auction.clear_bid(&kb, &mut b).unwrap();
+        auction.clear_bid(&kc, &mut c).unwrap();
+        auction.clear_bid(&kd, &mut d).unwrap();
+        assert_eq!(auction.clearing_head, None);
+        assert_eq!(auction.clear_bid(&ka, &mut a), Err(AuctionError::InvalidBidRecord));
+
+        assert_eq!((a.filled, b.filled, c.filled, d.filled), (60, 30, 10, 0));
+        assert_eq!(auction.units_allocated, 100);
+        assert_eq!(auction.clearing_price, 10);
+        assert_eq!(auction.processed_count, 4);
+    }
+
+    #[test]
+    fn candle_close_comes_from_the_first_slot_at_or_after_the_target() {
+        let slot_hashes = |slots: &[Slot]| {
+            let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+            for &slot in slots {
+                data.extend_from_slice(&slot.to_le_bytes());
+                data.extend_from_slice(&[slot as u8; 32]);
+            }
+            data
+        };
+        // slot 12 was skipped, 13 is the first at or after it
+        let data = slot_hashes(&[15, 14, 13, 11, 10]);
+        assert_eq!(find_slot_hash(&data, 12), Some([13; 32]));
+        assert_eq!(find_slot_hash(&data, 11), Some([11; 32]));
+        // no hash yet
+        assert_eq!(find_slot_hash(&data, 16), None);
+        // the history does not reach back before the slot any more
+        assert_eq!(find_slot_hash(&data, 10), None);
+        assert_eq!(find_slot_hash(&[], 10), None);
+    }
+
+    #[test]
+    fn candle_counts_the_total_at_the_close() {
+        let auction = AuctionV2 {
+            end_timestamp: 1_000,
+            candle_window: 100,
+            ..AuctionV2::default()
+        };
+        let mut record = BidRecord::default();
+        for (timestamp, amount) in [(800, 10), (850, 20), (920, 30), (990, 40)] {
+            record.amount = amount;
+            record.push_timed_bid(timestamp, auction.candle_window_start()).unwrap();
+        }
+        // only the last bid before the window is kept
+        assert_eq!(record.bid_history.len(), 3);
+        assert_eq!(record.amount_at(899), Some(20));
+        assert_eq!(record.amount_at(920), Some(30));
+        assert_eq!(record.amount_at(1_000), Some(40));
+        assert_eq!(record.amount_at(700), None);
+
+        let close = auction.draw_candle_close(&[0xff; 32]);
+        assert!((900..=1_000).contains(&close));
+    }
+
+    #[test]
+    fn v1_auctions_migrate_to_v2() {
+        let v1 = AuctionV1 {
+            is_initialized: true,
+            start_timestamp: 1_650_000_000,
+            end_timestamp: 1_650_086_400,
+            canceled: 1,
+            ..AuctionV1::default()
+        };
+        let mut data = vec![0; 1 + AuctionV1::LEN];
+        AuctionVersion::pack(AuctionVersion::AuctionV1(v1), &mut data).unwrap();
+        let end = 1 + 1 + 4 * 32 + AuctionFees::LEN + 8 + 8;
+        assert_eq!(&data[end..end + 8], &1_650_086_400i64.to_le_bytes());
+        assert_eq!(
+            AuctionVersion::unpack_latest(&data),
+            Err(AuctionError::OutdatedAuction.into())
+        );
+        let v1 = AuctionV1::unpack(&data[1..]).unwrap();
+        assert_eq!(v1.status(), AuctionStatus::Canceled);
+
+        let mut auction = AuctionV2::from(v1);
+        assert_eq!(auction.status, AuctionStatus::Canceled);
+        assert_eq!(auction.end_timestamp, 1_650_086_400);
+        assert_eq!(auction.max_winners, 1);
+
+        auction.track_bid(1_650_000_100);
+        let mut data = vec![0; AuctionVersion::LATEST_LEN];
+        AuctionVersion::pack(AuctionVersion::AuctionV2(auction), &mut data).unwrap();
+        let unpacked = AuctionVersion::unpack_latest(&data).unwrap();
+        assert_eq!(unpacked.start_timestamp, 1_650_000_000);
+        assert_eq!(unpacked.bids_placed, 1);
+        assert_eq!(unpacked.last_bid_timestamp, 1_650_000_100);
+        assert_eq!(AuctionVersion::unpack(&data).unwrap().canceled(), 1);
+    }
+
+    #[test]
+    fn auction_addresses_are_derived_from_seller_mint_and_index() {
+        let program_id = Pubkey::new_unique();
+        let seller = Pubkey::new_unique();
+        let lot_mint = Pubkey::new_unique();
+        let (address, bump_seed) = find_auction_address(&program_id, &seller, &lot_mint, 7);
+        assert_eq!(
+            Pubkey::create_program_address(
+                &[
+                    AUCTION_SEED,
+                    &seller.to_bytes(),
+                    &lot_mint.to_bytes(),
+                    &7u64.to_le_bytes(),
+                    &[bump_seed],
+                ],
+                &program_id,
+            ),
+            Ok(address)
+        );
+        assert_ne!(find_auction_address(&program_id, &seller, &lot_mint, 8).0, address);
+        assert_ne!(
+            find_auction_address(&program_id, &lot_mint, &seller, 7).0,
+            address
+        );
+    }
+
+    #[test]
+    fn borsh_encodings_fit_their_accounts() {
+        let winners = vec![WinningBid::default(); MAX_WINNERS];
+        let auction = AuctionV2 {
+            is_initialized: true,
+            winners: winners.clone(),
+            standby: winners,
+            reserve_commitment: Some([0; 32]),
+            allowlist_root: Some([0; 32]),
+            dutch_price: DutchPrice {
+                curve: ExponentialCurve::default().into(),
+                ..DutchPrice::default()
+            },
+            clearing_head: Some(Pubkey::new_unique()),
+            ..AuctionV2::default()
+        };
+        assert_eq!(auction.try_to_vec().unwrap().len(), AuctionV2::MAX_LEN);
+        let mut data = vec![0; AuctionVersion::LATEST_LEN];
+        AuctionVersion::pack(AuctionVersion::AuctionV2(auction.clone()), &mut data).unwrap();
+        assert_eq!(data[0], BORSH_AUCTION_VERSION);
+        assert_eq!(AuctionVersion::unpack_latest(&data).unwrap(), auction);
+
+        let bid_record = BidRecord {
+            is_initialized: true,
+            commitment: Some([0; 32]),
+            bid_history: vec![TimedBid::default(); MAX_BID_HISTORY],
+            next_bid: Some(Pubkey::new_unique()),
+            ..BidRecord::default()
+        };
+        let mut data = vec![0; BidRecord::SPACE];
+        BidRecord::pack_versioned(bid_record.clone(), &mut data).unwrap();
+        assert_eq!(data[0], BidRecord::TAG);
+        assert_eq!(BidRecord::unpack_versioned(&data).unwrap(), bid_record);
+
+        let config = ProgramConfig {
+            is_initialized: true,
+            fee_owner: Some(Pubkey::new_unique()),
+            ..ProgramConfig::default()
+        };
+        let mut data = vec![0; ProgramConfig::SPACE];
+        ProgramConfig::pack_versioned(config.clone(), &mut data).unwrap();
+        assert_eq!(data[0], ProgramConfig::TAG);
+        assert_eq!(ProgramConfig::unpack_versioned(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn packed_accounts_stay_readable() {
+        let bid_record = BidRecord {
+            is_initialized: true,
+            amount: 500,
+            ..BidRecord::default()
+        };
+        let mut data = vec![0; BidRecord::LEN];
+        BidRecord::pack_versioned(bid_record.clone(), &mut data).unwrap();
+        assert_eq!(data[0], 1);
+        assert_eq!(BidRecord::unpack_versioned(&data).unwrap(), bid_record);
+    }
+
+    proptest! {
+        #[test]
+        fn pack_round_trip(
+            v1 in auction_v1s(),
+            auction in auction_v2s(),
+            bids_placed: u64,
+            last_bid_timestamp: i64,
+            auction_index: u64,
+            bump_seed: u8,
+        ) {
+            let mut packed = vec![0u8; AuctionV1::LEN];
+            Pack::pack_into_slice(&v1, &mut packed);
+            prop_assert_eq!(AuctionV1::unpack_from_slice(&packed).unwrap(), v1);
+
+            let auction = AuctionV2 {
+                is_initialized: true,
+                bids_placed,
+                last_bid_timestamp,
+                auction_index,
+                bump_seed,
+                ..auction
+            };
+            let mut data = vec![0u8; AuctionVersion::LATEST_LEN];
+            AuctionVersion::pack(AuctionVersion::AuctionV2(auction.clone()), &mut data).unwrap();
+            prop_assert_eq!(AuctionVersion::unpack_latest(&data).unwrap(), auction);
+        }
+    }
+}
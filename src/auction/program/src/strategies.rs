@@ -0,0 +1,295 @@
+//! Proptest strategies for the packed types, shared by the round-trip tests
+
+use crate::{
+    curve::{
+        base::DutchPrice, calculator::DutchCurve, exponential::ExponentialCurve,
+        linear::LinearCurve, stepped::SteppedCurve,
+    },
+    fees::{AuctionFees, FeeShare, FeeSplits},
+    rules::{BidRules, PenaltyRecipient, RetractPolicy, RetractRules, SoftClose, WalletCaps},
+    state::{
+        AuctionStatus, AuctionType, AuctionV1, AuctionV2, PricingRule, WinningBid,
+        MAX_WINNERS,
+    },
+};
+use proptest::{collection::vec, option, prelude::*};
+use solana_program::pubkey::Pubkey;
+use std::convert::TryFrom;
+
+pub fn pubkeys() -> impl Strategy<Value = Pubkey> {
+    any::<[u8; 32]>().prop_map(Pubkey::new_from_array)
+}
+
+pub fn hash_options() -> impl Strategy<Value = Option<[u8; 32]>> {
+    option::of(any::<[u8; 32]>())
+}
+
+pub fn auction_fees() -> impl Strategy<Value = AuctionFees> {
+    (any::<u64>(), any::<u64>()).prop_map(|(auction_fee_numerator, auction_fee_denominator)| {
+        AuctionFees {
+            auction_fee_numerator,
+            auction_fee_denominator,
+        }
+    })
+}
+
+pub fn fee_splits() -> impl Strategy<Value = FeeSplits> {
+    let shares = || (any::<u16>(), pubkeys()).prop_map(|(bps, account)| FeeShare { bps, account });
+    (shares(), shares()).prop_map(|(royalty, marketplace)| FeeSplits {
+        royalty,
+        marketplace,
+    })
+}
+
+pub fn bid_rules() -> impl Strategy<Value = BidRules> {
+    (any::<u64>(), any::<u64>(), any::<u16>()).prop_map(
+        |(starting_price, min_increment, min_increment_bps)| BidRules {
+            starting_price,
+            min_increment,
+            min_increment_bps,
+        },
+    )
+}
+
+pub fn soft_closes() -> impl Strategy<Value = SoftClose> {
+    (any::<i64>(), any::<i64>(), any::<i64>()).prop_map(
+        |(extension_window, extension_duration, max_extension)| SoftClose {
+            extension_window,
+            extension_duration,
+            max_extension,
+        },
+    )
+}
+
+pub fn retract_rules() -> impl Strategy<Value = RetractRules> {
+    (0u8..3, any::<u16>(), 0u8..2).prop_map(|(policy, penalty_bps, penalty_recipient)| {
+        RetractRules {
+            policy: RetractPolicy::try_from(policy).unwrap(),
+            penalty_bps,
+            penalty_recipient: PenaltyRecipient::try_from(penalty_recipient).unwrap(),
+        }
+    })
+}
+
+pub fn wallet_caps() -> impl Strategy<Value = WalletCaps> {
+    (any::<u64>(), any::<u64>()).prop_map(|(max_bid_per_wallet, max_units_per_wallet)| {
+        WalletCaps {
+            max_bid_per_wallet,
+            max_units_per_wallet,
+        }
+    })
+}
+
+pub fn dutch_prices() -> impl Strategy<Value = DutchPrice> {
+    let curves = prop_oneof![
+        Just(DutchCurve::from(LinearCurve)),
+        (any::<i64>(), any::<u16>())
+            .prop_map(|(period, decay_bps)| ExponentialCurve { period, decay_bps }.into()),
+        any::<i64>().prop_map(|step_duration| SteppedCurve { step_duration }.into()),
+    ];
+    (any::<u64>(), any::<u64>(), curves).prop_map(|(start_price, floor_price, curve)| {
+        DutchPrice {
+            start_price,
+            floor_price,
+            curve,
+        }
+    })
+}
+
+pub fn auction_types() -> impl Strategy<Value = AuctionType> {
+    (0u8..5).prop_map(|auction_type| AuctionType::try_from(auction_type).unwrap())
+}
+
+pub fn auction_statuses() -> impl Strategy<Value = AuctionStatus> {
+    (0u8..3).prop_map(|status| AuctionStatus::try_from(status).unwrap())
+}
+
+pub fn pricing_rules() -> impl Strategy<Value = PricingRule> {
+    (0u8..2).prop_map(|pricing_rule| PricingRule::try_from(pricing_rule).unwrap())
+}
+
+pub fn winning_bids() -> impl Strategy<Value = Vec<WinningBid>> {
+    vec(
+        (pubkeys(), any::<u64>()).prop_map(|(bidder, amount)| WinningBid { bidder, amount }),
+        0..=MAX_WINNERS,
+    )
+}
+
+pub fn auction_v1s() -> impl Strategy<Value = AuctionV1> {
+    (
+        any::<bool>(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+        auction_fees(),
+        any::<u8>(),
+        any::<i64>(),
+        any::<i64>(),
+        any::<u8>(),
+    )
+        .prop_map(
+            |(
+                is_initialized,
+                token_program_id,
+                token,
+                pool,
+                fee_account,
+                fees,
+                nonce,
+                start_timestamp,
+                end_timestamp,
+                canceled,
+            )| AuctionV1 {
+                is_initialized,
+                token_program_id,
+                token,
+                pool,
+                fee_account,
+                fees,
+                nonce,
+                start_timestamp,
+                end_timestamp,
+                canceled,
+            },
+        )
+}
+
+pub fn auction_v2s() -> impl Strategy<Value = AuctionV2> {
+    let accounts = (
+        any::<bool>(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+        pubkeys(),
+    );
+    let timing = (
+        auction_fees(),
+        any::<u8>(),
+        any::<i64>(),
+        any::<i64>(),
+        auction_statuses(),
+        any::<i64>(),
+        any::<i64>(),
+        any::<i64>(),
+        any::<i64>(),
+    );
+    let bidding = (
+        any::<u8>(),
+        winning_bids(),
+        any::<u64>(),
+        any::<u64>(),
+        hash_options(),
+        bid_rules(),
+        soft_closes(),
+        auction_types(),
+        dutch_prices(),
+        any::<u64>(),
+    );
+    let clearing = (
+        any::<u64>(),
+        pricing_rules(),
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        option::of(pubkeys()),
+        any::<u64>(),
+    );
+    let extensions = (
+        any::<bool>(),
+        retract_rules(),
+        winning_bids(),
+        hash_options(),
+        wallet_caps(),
+        fee_splits(),
+    );
+    (accounts, timing, bidding, clearing, extensions).prop_map(
+        |(
+            (is_initialized, token_program_id, token, pool, fee_account, lot_mint, lot_vault, authority),
+            (
+                fees,
+                nonce,
+                start_timestamp,
+                end_timestamp,
+                status,
+                total_extension,
+                reveal_end_timestamp,
+                candle_window,
+                candle_close_timestamp,
+            ),
+            (
+                max_winners,
+                winners,
+                lot_amount,
+                reserve_price,
+                reserve_commitment,
+                bid_rules,
+                soft_close,
+                auction_type,
+                dutch_price,
+                min_deposit,
+            ),
+            (
+                unrevealed_deposits,
+                pricing_rule,
+                runner_up_bid,
+                bid_count,
+                processed_count,
+                units_allocated,
+                clearing_price,
+                clearing_head,
+                buy_now_price,
+            ),
+            (native, retract_rules, standby, allowlist_root, wallet_caps, fee_splits),
+        )| AuctionV2 {
+            is_initialized,
+            token_program_id,
+            token,
+            pool,
+            fee_account,
+            fees,
+            nonce,
+            start_timestamp,
+            end_timestamp,
+            status,
+            max_winners,
+            winners,
+            lot_mint,
+            lot_vault,
+            lot_amount,
+            authority,
+            reserve_price,
+            reserve_commitment,
+            bid_rules,
+            soft_close,
+            total_extension,
+            auction_type,
+            dutch_price,
+            reveal_end_timestamp,
+            min_deposit,
+            unrevealed_deposits,
+            pricing_rule,
+            runner_up_bid,
+            bid_count,
+            processed_count,
+            units_allocated,
+            clearing_price,
+            clearing_head,
+            buy_now_price,
+            candle_window,
+            candle_close_timestamp,
+            native,
+            retract_rules,
+            standby,
+            allowlist_root,
+            wallet_caps,
+            fee_splits,
+            ..AuctionV2::default()
+        },
+    )
+}